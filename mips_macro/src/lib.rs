@@ -0,0 +1,262 @@
+//! Assembles MIPS code at compile time into a `[u32; N]` array
+//! with the words of the text section.
+//!
+//! The source can be written directly as tokens, one instruction per line:
+//!
+//! ```
+//! use mips_macro::mips;
+//!
+//! // addi, bne and its delay slot, jr and its delay slot
+//! const PROGRAM: [u32; 5] = mips! {
+//!     loop: addi $t0, $t0, -1
+//!     bne $t0, $zero, loop
+//!     jr $ra
+//! };
+//! assert_eq!(PROGRAM[0], 0x2108ffff);
+//! ```
+//!
+//! or as a string literal:
+//!
+//! ```
+//! use mips_macro::mips;
+//!
+//! const PROGRAM: [u32; 1] = mips!("add $t0, $t1, $t2");
+//! assert_eq!(PROGRAM, [0x012a4020]);
+//! ```
+//!
+//! Raw strings keep the backslashes of character literals and are
+//! convenient for several lines:
+//!
+//! ```
+//! use mips_macro::mips;
+//!
+//! const PROGRAM: [u32; 2] = mips!(r#"
+//!     addi $t0, $zero, '\n'
+//!     addi $t1, $zero, '"'
+//! "#);
+//! assert_eq!(PROGRAM, [0x2008000a, 0x20090022]);
+//! ```
+//!
+//! Assembly errors are compile errors pointing at the offending line:
+//!
+//! ```compile_fail
+//! use mips_macro::mips;
+//!
+//! const PROGRAM: [u32; 1] = mips! { add $t0, $t1 };
+//! ```
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Source rebuilt from the macro input, with the span of
+/// the first token of each line to report errors
+struct Source {
+    text: String,
+    lines: Vec<Span>,
+}
+
+#[proc_macro]
+pub fn mips(input: TokenStream) -> TokenStream {
+    let source = match string_literal(&input) {
+        Some((text, span)) => Source {
+            lines: vec![span; text.lines().count().max(1)],
+            text,
+        },
+        None => rebuild_source(input),
+    };
+
+    match assembler::assemble_str(&source.text, &Default::default()) {
+        Ok(program) => {
+            let words = program
+                .section(".text")
+                .map(|text| text.words.clone())
+                .unwrap_or_default();
+            array(&words)
+        }
+        Err(diagnostics) => {
+            let errors = diagnostics
+                .iter()
+                .map(|diagnostic| {
+                    let span = source
+                        .lines
+                        .get(diagnostic.line - 1)
+                        .copied()
+                        .unwrap_or_else(Span::call_site);
+                    compile_error(&diagnostic.message, span)
+                })
+                .collect();
+            TokenTree::Group(Group::new(Delimiter::Brace, errors)).into()
+        }
+    }
+}
+
+/// Contents of the input when it is a single string literal
+fn string_literal(input: &TokenStream) -> Option<(String, Span)> {
+    let mut tokens = input.clone().into_iter();
+    let literal = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal,
+        _ => return None,
+    };
+
+    let text = literal.to_string();
+    if let Some(raw) = text.strip_prefix('r') {
+        // r"...", r#"..."#, with any number of '#'
+        let hashes = &raw[..raw.len() - raw.trim_start_matches('#').len()];
+        let contents = raw
+            .strip_prefix(hashes)?
+            .strip_suffix(hashes)?
+            .strip_prefix('"')?
+            .strip_suffix('"')?;
+        return Some((contents.to_string(), literal.span()));
+    }
+    let contents = text.strip_prefix('"')?.strip_suffix('"')?;
+    Some((unescape(contents)?, literal.span()))
+}
+
+/// Replaces the escapes of a string literal, which the
+/// compiler has already checked, with what they stand for
+fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => unescaped.push('\n'),
+            't' => unescaped.push('\t'),
+            'r' => unescaped.push('\r'),
+            '0' => unescaped.push('\0'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                unescaped.push(u8::from_str_radix(&hex, 16).ok()? as char);
+            }
+            'u' => {
+                let hex: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                unescaped.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            // A backslash at the end of a line skips the line
+            // break and the indentation after it
+            '\n' => while chars.next_if(|c| c.is_whitespace()).is_some() {},
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+/// Rebuilds the source from the tokens, keeping their lines
+/// and separating tokens that had a gap between them
+fn rebuild_source(input: TokenStream) -> Source {
+    let mut source = Source {
+        text: String::new(),
+        lines: Vec::new(),
+    };
+    let mut last_line = None;
+    let mut last_end = 0;
+    push_tokens(input, &mut source, &mut last_line, &mut last_end);
+    source
+}
+
+fn push_tokens(
+    input: TokenStream,
+    source: &mut Source,
+    last_line: &mut Option<usize>,
+    last_end: &mut usize,
+) {
+    for token in input {
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::None => ("", ""),
+                };
+                push_text(open, group.span_open(), source, last_line, last_end);
+                push_tokens(group.stream(), source, last_line, last_end);
+                push_text(close, group.span_close(), source, last_line, last_end);
+            }
+            token => {
+                let text = token.to_string();
+                push_text(&text, token.span(), source, last_line, last_end);
+            }
+        }
+    }
+}
+
+fn push_text(
+    text: &str,
+    span: Span,
+    source: &mut Source,
+    last_line: &mut Option<usize>,
+    last_end: &mut usize,
+) {
+    if text.is_empty() {
+        return;
+    }
+
+    if *last_line != Some(span.line()) {
+        if last_line.is_some() {
+            source.text.push('\n');
+        }
+        source.lines.push(span);
+        *last_line = Some(span.line());
+    } else if span.column() > *last_end {
+        source.text.push(' ');
+    }
+
+    source.text.push_str(text);
+    *last_end = span.end().column();
+}
+
+/// `[word, word, ...]` with every word typed as u32
+fn array(words: &[u32]) -> TokenStream {
+    let mut elements = TokenStream::new();
+    for word in words {
+        elements.extend([
+            TokenTree::Literal(Literal::u32_suffixed(*word)),
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+        ]);
+    }
+    TokenTree::Group(Group::new(Delimiter::Bracket, elements)).into()
+}
+
+/// `::core::compile_error!("message")` located at `span`
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut message = Literal::string(message);
+    message.set_span(span);
+    let mut arguments = Group::new(Delimiter::Parenthesis, TokenTree::Literal(message).into());
+    arguments.set_span(span);
+
+    let tokens: Vec<TokenTree> = vec![
+        Punct::new(':', Spacing::Joint).into(),
+        Punct::new(':', Spacing::Alone).into(),
+        Ident::new("core", span).into(),
+        Punct::new(':', Spacing::Joint).into(),
+        Punct::new(':', Spacing::Alone).into(),
+        Ident::new("compile_error", span).into(),
+        Punct::new('!', Spacing::Alone).into(),
+        arguments.into(),
+        Punct::new(';', Spacing::Alone).into(),
+    ];
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unescape;
+
+    #[test]
+    fn unescapes_in_one_pass() {
+        assert_eq!(unescape(r"'\\n'").as_deref(), Some(r"'\n'"));
+        assert_eq!(unescape(r#"a\nb\t\""#).as_deref(), Some("a\nb\t\""));
+        assert_eq!(unescape(r"\x41\u{42}").as_deref(), Some("AB"));
+        assert_eq!(unescape("a\\\n    b").as_deref(), Some("ab"));
+    }
+}
@@ -1,251 +1,1147 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Write},
+    io::{self, Write},
+    path::PathBuf,
 };
 
 use crate::{
-    instruction::{self, Instruction},
-    instruction_format::InstructionFormat,
-    label::Label,
-    register::Register,
+    delay_slot,
+    diagnostic::Diagnostic,
+    emitter::Emitter,
+    expression::{Constant, Context, Expression, Value},
+    hazard::{self, Hazard, PipelineConfig},
+    instruction_format::{self, InstructionFormat},
+    label::{Label, Segment},
+    layout::{self, Layout, Placement},
+    preprocessor::{self, Origin, SourceLine},
+    program::{Program, Section, Symbol, GP_ADDRESS, TEXT_ADDRESS},
+    pseudo,
+    statement::{self, Operand, Statement},
 };
 
-pub struct Assembler<'a> {
-    file_to_read: &'a str,
+/// Settings that change how a source is assembled
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Fill delay slots with the previous instruction
+    /// instead of a nop when it is safe
+    pub optimize: bool,
+    /// Insert the nops needed to run on this pipeline
+    pub insert_nops: Option<PipelineConfig>,
+    /// Address of the first instruction
+    pub text_address: u32,
+    /// Directories searched for ".include" files not found
+    /// next to the file that includes them
+    pub include_paths: Vec<PathBuf>,
+    /// Constants defined before the source is read,
+    /// as with ".eqv NAME value"
+    pub defines: Vec<(String, i64)>,
+    /// Word between sections in a memory image, such as the
+    /// gap left before code placed with ".org"
+    pub fill: u32,
+    /// Value $gp holds while the program runs, which ".sdata"
+    /// and ".sbss" are accessed from
+    pub gp: u32,
+}
+
+/// Source to assemble and the file it was read from, used in
+/// diagnostics and to find the files it includes
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// None for a source only held in memory
+    pub path: Option<String>,
+    pub text: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            optimize: false,
+            insert_nops: None,
+            text_address: TEXT_ADDRESS,
+            include_paths: Vec::new(),
+            defines: Vec::new(),
+            fill: 0,
+            gp: GP_ADDRESS,
+        }
+    }
+}
+
+/// Double precision values take an even register and the one after
+/// it, so odd registers are rejected where an operand is a double
+fn check_double_registers(statement: &Statement) -> Result<(), String> {
+    for (index, operand) in statement.operands.iter().enumerate() {
+        if let Operand::FloatRegister(register) = operand {
+            if statement.is_double(index) && register % 2 != 0 {
+                return Err(format!(
+                    "Registrador de precisao dupla deve ser par: $f{}",
+                    register
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Labels defined in ".sdata" or ".sbss", found before parsing
+/// so that uses written before the definition are also reached from $gp
+fn small_data_labels(lines: &[SourceLine]) -> Vec<String> {
+    let mut small = false;
+    let mut labels = Vec::new();
+    for line in lines {
+        let mut words = line.text.split_whitespace().peekable();
+        if let Some(label) = words.next_if(|word| Label::is_label(word)) {
+            let name = &label[..label.len() - 1];
+            if small && !Label::is_numeric(name) {
+                labels.push(name.to_string());
+            }
+        }
+        if let Some(section) = words.next().filter(|word| layout::SECTIONS.contains(word)) {
+            small = layout::SMALL_SECTIONS.contains(&section);
+        }
+    }
+    labels
+}
+
+/// Value of a ".word" directive, evaluated once all labels are known
+struct DataWord {
+    value: Expression,
+    line: usize,
+    origin: Origin,
+}
+
+pub struct Assembler {
+    statements: Vec<Statement>,
+    data: Vec<DataWord>,
     labels: Vec<Label>,
+    constants: Vec<Constant>,
+    layout: Layout,
+    fill: u32,
+    /// Labels in ".sdata" and ".sbss", accessed from $gp
+    small_data: Vec<String>,
+    /// Outputs the assembled program is handed to
+    emitters: Vec<(Box<dyn Emitter>, Box<dyn Write>)>,
 }
 
-impl<'a> Assembler<'a> {
-    /// Assemble a given file and writes the result
-    /// binary in a new file
-    pub fn assemble(&self) {
-        let file_in = File::open(self.file_to_read).expect("Erro ao ler arquivo de entrada");
-        let reader = BufReader::new(file_in);
-        let mut lines_written = 0;
-
-        let mut file_out = File::create("out.bin").expect("Erro ao criar arquivo de saída");
-        file_out
-            .write(b"v2.0 raw\n")
-            .expect("Erro ao escrever no arquivo");
-
-        for (index, line) in reader.lines().enumerate() {
-            // Lines start at 1
-            let current_line_number = index + 1;
-            let line = line.expect("Erro ao ler linha");
-
-            // each line should respect the following pattern:
-            // -> words[0] = instruction (such as "add" or "jal")
-            // -> word[1] = rd (in R instructions), rs (in I instructions),
-            // or constant (in J instructions)
-            // -> word[2] = rs (in R instructions), or rt (in I instructions)
-            // -> word[3] = rt or shamt (in R instructions), or constant (in I instructions)
-            let mut words: Vec<&str> = line.split_whitespace().collect();
-
-            if words.len() < 1 {
+impl Assembler {
+    /// Encodes the parsed source into a program
+    pub fn assemble(&self) -> Result<Program, Vec<Diagnostic>> {
+        let mut words = Vec::new();
+        let mut diagnostics = Vec::new();
+
+        for (index, statement) in self.statements.iter().enumerate() {
+            match self.encode(index, statement) {
+                Ok(word) => words.push(word),
+                Err(message) => {
+                    diagnostics.push(statement.origin.diagnostic(statement.line, message))
+                }
+            }
+        }
+
+        let mut data = Vec::new();
+        for (index, word) in self.data.iter().enumerate() {
+            let location = self.layout.address(Segment::Data, index);
+            match self.data_word(&word.value, location, word.origin.file.as_deref()) {
+                Ok(value) => data.push(value),
+                Err(message) => diagnostics.push(word.origin.diagnostic(word.line, message)),
+            }
+        }
+
+        diagnostics.extend(self.check_overlaps());
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        Ok(Program {
+            sections: self.sections(&words, &data),
+            symbols: self
+                .labels
+                .iter()
+                .filter(|label| !Label::is_local(&label.name))
+                .map(|label| Symbol {
+                    name: label.name.clone(),
+                    address: label.address(&self.layout),
+                })
+                .collect(),
+            fill: self.fill,
+        })
+    }
+
+    /// Length of the segment, in instructions or data words
+    fn segment_length(&self, segment: Segment) -> usize {
+        match segment {
+            Segment::Text => self.statements.len(),
+            Segment::Data => self.data.len(),
+        }
+    }
+
+    /// One section for each chunk of the layout, joining a chunk to the
+    /// previous one of the same section when it starts right after it.
+    /// The first ".text" and ".data" are kept even when empty
+    fn sections(&self, words: &[u32], data: &[u32]) -> Vec<Section> {
+        let mut sections: Vec<Section> = Vec::new();
+        for (index, chunk) in self.layout.chunks().iter().enumerate() {
+            let segment = chunk.segment();
+            let end = self.layout.end(index);
+            let values = match segment {
+                Segment::Text => &words[chunk.start..end],
+                Segment::Data => &data[chunk.start..end],
+            };
+            if values.is_empty() && index >= 2 {
                 continue;
             }
 
-            // Does not consider the label when parsing the line
-            // because labels were already parsed
-            if Label::is_label(words[0]) {
-                words.remove(0);
+            let previous = sections.iter_mut().rev().find(|section| {
+                section.name == chunk.section
+                    && section.address.wrapping_add(section.words.len() as u32 * 4) == chunk.address
+            });
+            match previous {
+                Some(section) => section.words.extend_from_slice(values),
+                None => sections.push(Section {
+                    name: chunk.section.to_string(),
+                    address: chunk.address,
+                    words: values.to_vec(),
+                }),
             }
+        }
+        sections
+    }
 
-            let instruction =
-                Instruction::get_instruction(words[0]).expect("Instrucao nao encontrada");
-            let mut binary = String::new();
+    /// Chunks placed over the addresses of an earlier chunk, reported
+    /// at the first instruction or data word of the later one
+    fn check_overlaps(&self) -> Vec<Diagnostic> {
+        let chunks = self.layout.chunks();
+        let ranges: Vec<(u64, u64)> = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let length = self.layout.end(index) - chunk.start;
+                let start = chunk.address as u64;
+                (start, start + length as u64 * 4)
+            })
+            .collect();
 
-            match instruction.format {
-                InstructionFormat::R { op_code, function } => {
-                    let mut push_binary = || {
-                        if ["mult", "multu", "div", "divu"].contains(&instruction.name) {
-                            let rs = Register::get_register(words[1]);
-                            let rt = Register::get_register(words[2]);
-                            binary.push_str(&format!(
-                                "{:06b}{:05b}{:05b}{:05b}{:05b}{:06b}",
-                                op_code, rs, rt, 0, 0, function
-                            ));
-                            return;
-                        } else if ["jr", "mfhi", "mflo"].contains(&instruction.name) {
-                            if instruction.name == "jr" {
-                                let rs = Register::get_register(words[1]);
-                                binary.push_str(&format!(
-                                    "{:06b}{:05b}{:05b}{:05b}{:05b}{:06b}",
-                                    op_code, rs, 0, 0, 0, function
-                                ));
-                                return;
-                            }
-                            let rd = Register::get_register(words[1]);
-                            binary.push_str(&format!(
-                                "{:06b}{:05b}{:05b}{:05b}{:05b}{:06b}",
-                                op_code, 0, 0, rd, 0, function
+        let mut diagnostics = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let (start, end) = ranges[index];
+            let overlap = (0..index).find(|&other| {
+                let (other_start, other_end) = ranges[other];
+                start < end && other_start < other_end && start < other_end && other_start < end
+            });
+            if let Some(other) = overlap {
+                let message = format!(
+                    "Sobreposicao com {} em 0x{:08x}",
+                    chunks[other].section,
+                    start.max(ranges[other].0)
+                );
+                let (line, origin) = match chunk.segment() {
+                    Segment::Text => {
+                        let statement = &self.statements[chunk.start];
+                        (statement.line, &statement.origin)
+                    }
+                    Segment::Data => {
+                        let word = &self.data[chunk.start];
+                        (word.line, &word.origin)
+                    }
+                };
+                diagnostics.push(origin.diagnostic(line, message));
+            }
+        }
+        diagnostics
+    }
+
+    /// Writes the program to `out` with `emitter` when [`emit`](Assembler::emit) runs
+    pub fn add_emitter(&mut self, emitter: Box<dyn Emitter>, out: Box<dyn Write>) {
+        self.emitters.push((emitter, out));
+    }
+
+    /// Hands the program to every emitter, in the order they were added
+    pub fn emit(&mut self, program: &Program) -> io::Result<()> {
+        for (emitter, out) in &mut self.emitters {
+            emitter.emit(program, out)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    fn encode(&self, index: usize, statement: &Statement) -> Result<u32, String> {
+        let instruction = statement.instruction;
+        check_double_registers(statement)?;
+
+        let word = match instruction.format {
+            InstructionFormat::R { op_code, function }
+                if matches!(instruction.name, "syscall" | "break") =>
+            {
+                // The code takes the place of the registers and shamt
+                let code = self.code(statement.constant(0), index)?;
+                InstructionFormat::encode_r(op_code, 0, 0, 0, 0, function) | code << 6
+            }
+            InstructionFormat::R { op_code, function } => {
+                let (rs, rt, rd, shamt) = match instruction.name {
+                    "nop" => (0, 0, 0, 0),
+                    "mult" | "multu" | "div" | "divu" | "madd" | "maddu" | "msub" | "msubu"
+                    | "tge" | "tgeu" | "tlt" | "tltu" | "teq" | "tne" => {
+                        (statement.register(0), statement.register(1), 0, 0)
+                    }
+                    "jr" => (statement.register(0), 0, 0, 0),
+                    "mfhi" | "mflo" => (0, 0, statement.register(0), 0),
+                    "sll" | "srl" => {
+                        let shamt = self.shamt(statement.constant(2), index)?;
+                        (0, statement.register(1), statement.register(0), shamt)
+                    }
+                    // rd is repeated in rt, as the architecture requires
+                    "clz" | "clo" => (
+                        statement.register(1),
+                        statement.register(0),
+                        statement.register(0),
+                        0,
+                    ),
+                    _ => (
+                        statement.register(1),
+                        statement.register(2),
+                        statement.register(0),
+                        0,
+                    ),
+                };
+                InstructionFormat::encode_r(op_code, rs, rt, rd, shamt, function)
+            }
+            InstructionFormat::I { op_code } => {
+                let (rs, rt, constant) = match instruction.name {
+                    "lw" | "sw" | "lwc1" | "swc1" | "ldc1" | "sdc1" => (
+                        statement.register(1),
+                        statement.register(0),
+                        self.immediate(statement.constant(1), index)?,
+                    ),
+                    "lui" => (
+                        0,
+                        statement.register(0),
+                        self.immediate(statement.constant(1), index)?,
+                    ),
+                    "beq" | "bne" => (
+                        statement.register(0),
+                        statement.register(1),
+                        self.branch_offset(statement.constant(2), index)?,
+                    ),
+                    _ => (
+                        statement.register(1),
+                        statement.register(0),
+                        self.immediate(statement.constant(2), index)?,
+                    ),
+                };
+                InstructionFormat::encode_i(op_code, rs, rt, constant)
+            }
+            InstructionFormat::RegImm { rt } => InstructionFormat::encode_i(
+                instruction_format::REGIMM,
+                statement.register(0),
+                rt,
+                self.immediate(statement.constant(1), index)?,
+            ),
+            InstructionFormat::Cop0 { rs, function } => {
+                // mfc0 and mtc0 take the general purpose register first
+                let (rt, rd) = match instruction.name {
+                    "eret" => (0, 0),
+                    _ => (statement.register(0), statement.register(1)),
+                };
+                InstructionFormat::encode_r(instruction_format::COP0, rs, rt, rd, 0, function)
+            }
+            InstructionFormat::FR { fmt, function } => {
+                let name = instruction.name;
+                let (ft, fs, fd) = match name {
+                    "mfc1" | "mtc1" => (statement.register(0), statement.register(1), 0),
+                    _ if name.starts_with("c.") => {
+                        (statement.register(1), statement.register(0), 0)
+                    }
+                    _ if statement.operands.len() == 3 => (
+                        statement.register(2),
+                        statement.register(1),
+                        statement.register(0),
+                    ),
+                    _ => (0, statement.register(1), statement.register(0)),
+                };
+                InstructionFormat::encode_r(instruction_format::COP1, fmt, ft, fs, fd, function)
+            }
+            InstructionFormat::FI { fmt, ft } => InstructionFormat::encode_i(
+                instruction_format::COP1,
+                fmt,
+                ft,
+                self.branch_offset(statement.constant(0), index)?,
+            ),
+            InstructionFormat::Special3 { function, shamt } => {
+                let (rs, rt, rd, shamt) = match instruction.name {
+                    "ext" | "ins" => {
+                        let position = self.evaluate(statement.constant(2), index)?.value;
+                        let size = self.evaluate(statement.constant(3), index)?.value;
+                        if !(0..32).contains(&position) || size < 1 || position + size > 32 {
+                            return Err(format!(
+                                "Campo de bits invalido para {}",
+                                instruction.name
                             ));
-                            return;
                         }
-                        let rd = Register::get_register(words[1]);
-                        let rs = Register::get_register(words[2]);
-                        let has_shamt = words[3].as_bytes()[0] != b'$';
-
-                        if has_shamt {
-                            let rt = rs;
-                            let shamt = words[3].parse::<u8>().expect("Shamt de 8 bits invalido");
-                            binary.push_str(&format!(
-                                "{:06b}{:05b}{:05b}{:05b}{:05b}{:06b}",
-                                op_code, 0, rt, rd, shamt, function
-                            ));
+                        // ext keeps size - 1 in rd, and ins the last bit of the field
+                        let last = if instruction.name == "ext" {
+                            size - 1
                         } else {
-                            let rt = Register::get_register(words[3]);
-                            binary.push_str(&format!(
-                                "{:06b}{:05b}{:05b}{:05b}{:05b}{:06b}",
-                                op_code, rs, rt, rd, 0, function
-                            ));
-                        }
-                    };
-                    push_binary();
-                }
-                InstructionFormat::I { op_code } => {
-                    let mut push_binary = || {
-                        if ["lw", "sw"].contains(&instruction.name) {
-                            let rt = Register::get_register(words[1]);
-                            let offset = &words[2];
-                            let rs = &words[3][1..words[3].len() - 1];
-
-                            binary.push_str(&format!(
-                                "{:06b}{:05b}{:05b}{:016b}",
-                                op_code,
-                                Register::get_register(rs),
-                                rt,
-                                offset.parse::<i16>().unwrap()
-                            ));
-                            return;
-                        }
+                            position + size - 1
+                        };
+                        (
+                            statement.register(1),
+                            statement.register(0),
+                            last as u8,
+                            position as u8,
+                        )
+                    }
+                    _ => (0, statement.register(1), statement.register(0), shamt),
+                };
+                InstructionFormat::encode_r(
+                    instruction_format::SPECIAL3,
+                    rs,
+                    rt,
+                    rd,
+                    shamt,
+                    function,
+                )
+            }
+            InstructionFormat::Rotate { function } => {
+                let (rs, shamt) = match instruction.name {
+                    "rotr" => (1, self.shamt(statement.constant(2), index)?),
+                    _ => (statement.register(2), 1),
+                };
+                InstructionFormat::encode_r(
+                    0,
+                    rs,
+                    statement.register(1),
+                    statement.register(0),
+                    shamt,
+                    function,
+                )
+            }
+            InstructionFormat::J { op_code } => {
+                // Addresses become a word address, while plain numbers
+                // are taken as the encoded target
+                let value = self.evaluate(statement.constant(0), index)?;
+                let target = if value.address {
+                    (value.value >> 2) as u32
+                } else {
+                    value.value as u32
+                };
 
-                        if &instruction.name == &"lui" {
-                            let rt = Register::get_register(words[1]);
-                            let constant = words[2];
+                // Conversion from a 32 bit integer to 26 bit integer
+                InstructionFormat::encode_j(op_code, target & 0x03FFFFFF)
+            }
+        };
 
-                            binary.push_str(&format!(
-                                "{:06b}{:05b}{:05b}{:016b}",
-                                op_code,
-                                0,
-                                rt,
-                                constant.parse::<i16>().unwrap()
-                            ));
-                            return;
-                        }
+        Ok(word)
+    }
 
-                        if ["beq", "bne"].contains(&instruction.name) {
-                            let rs = Register::get_register(words[1]);
-                            let rt = Register::get_register(words[2]);
-                            let constant = words[3];
-
-                            if Label::is_label_reference(&constant) {
-                                let relative_line = Label::reference_to_relative_line(
-                                    &self.labels,
-                                    &constant,
-                                    current_line_number,
-                                );
-                                binary.push_str(&format!(
-                                    "{:06b}{:05b}{:05b}{:016b}",
-                                    op_code, rs, rt, relative_line as i16
-                                ));
-                            } else {
-                                binary.push_str(&format!(
-                                    "{:06b}{:05b}{:05b}{:016b}",
-                                    op_code,
-                                    rs,
-                                    rt,
-                                    constant
-                                        .parse::<i16>()
-                                        .expect("Constante de 16 bits invalida")
-                                ));
-                            }
-                            return;
-                        }
+    /// Evaluates an operand of the instruction at `index`
+    fn evaluate(&self, expression: &str, index: usize) -> Result<Value, String> {
+        let context = Context {
+            labels: &self.labels,
+            constants: &self.constants,
+            layout: &self.layout,
+            location: self.layout.address(Segment::Text, index),
+            segment: Segment::Text,
+            file: self.statements[index].origin.file.as_deref(),
+        };
+        Expression::parse(expression)?.evaluate(&context)
+    }
 
-                        let rs = Register::get_register(words[2]);
-                        let rt = Register::get_register(words[1]);
-
-                        let constant = words[3];
-
-                        binary.push_str(&format!(
-                            "{:06b}{:05b}{:05b}{:016b}",
-                            op_code,
-                            rs,
-                            rt,
-                            constant.parse::<i16>().unwrap()
-                        ));
-                        return;
-                    };
-                    push_binary();
+    /// 5 bit shift amount
+    fn shamt(&self, expression: &str, index: usize) -> Result<u8, String> {
+        let shamt = self.evaluate(expression, index)?.value;
+        if !(0..32).contains(&shamt) {
+            return Err("Shamt de 5 bits invalido".to_string());
+        }
+        Ok(shamt as u8)
+    }
+
+    /// 20 bit code of a syscall or break, which the
+    /// exception handler can read from the instruction
+    fn code(&self, expression: &str, index: usize) -> Result<u32, String> {
+        let code = self.evaluate(expression, index)?.value;
+        if !(0..1 << 20).contains(&code) {
+            return Err(format!("Codigo de 20 bits invalido: {}", expression));
+        }
+        Ok(code as u32)
+    }
+
+    /// 16 bit immediate of the instruction at `index`, from 0 to 65535
+    /// when it is zero extended, or from -32768 to 32767 otherwise.
+    /// Negative values keep their two's complement bits
+    fn immediate(&self, expression: &str, index: usize) -> Result<u16, String> {
+        let value = self.evaluate(expression, index)?.value;
+        let range = if self.statements[index].instruction.zero_extends_immediate() {
+            0..=u16::MAX as i64
+        } else {
+            i16::MIN as i64..=i16::MAX as i64
+        };
+        if !range.contains(&value) {
+            return Err(format!("Constante de 16 bits invalida: {}", expression));
+        }
+        Ok(value as u16)
+    }
+
+    /// Offset of a branch, in instructions. Following the PC+4 rule,
+    /// an address is relative to the instruction after the branch,
+    /// which is its delay slot. A number is used as the offset itself
+    fn branch_offset(&self, expression: &str, index: usize) -> Result<u16, String> {
+        let value = self.evaluate(expression, index)?;
+        let offset = if value.address {
+            let next = self.layout.address(Segment::Text, index) as i64 + 4;
+            (value.value - next) >> 2
+        } else {
+            value.value
+        };
+        i16::try_from(offset)
+            .map(|offset| offset as u16)
+            .map_err(|_| format!("Desvio fora de alcance: {}", expression))
+    }
+
+    /// 32 bit word at `location` in the data segment
+    fn data_word(
+        &self,
+        expression: &Expression,
+        location: u32,
+        file: Option<&str>,
+    ) -> Result<u32, String> {
+        let context = Context {
+            labels: &self.labels,
+            constants: &self.constants,
+            layout: &self.layout,
+            location,
+            segment: Segment::Data,
+            file,
+        };
+        let value = expression.evaluate(&context)?.value;
+        if !(i32::MIN as i64..=u32::MAX as i64).contains(&value) {
+            return Err(format!("Constante de 32 bits invalida: {}", value));
+        }
+        Ok(value as u32)
+    }
+
+    /// Looks for pipeline hazards in the parsed program.
+    /// When `insert_nops` is set, nops are added to the
+    /// program so it can run on the given pipeline
+    pub fn check_hazards(&mut self, config: &PipelineConfig, insert_nops: bool) -> Vec<Hazard> {
+        if insert_nops {
+            hazard::insert_nops(
+                &mut self.statements,
+                &mut self.labels,
+                &mut self.layout,
+                config,
+            )
+        } else {
+            hazard::find_hazards(&self.statements, config)
+        }
+    }
+
+    /// Parses the given source, which is not read from a file
+    pub fn new(source: &str, options: &Options) -> Result<Assembler, Vec<Diagnostic>> {
+        let source = SourceFile {
+            path: None,
+            text: source.to_string(),
+        };
+        Assembler::from_sources(&[source], options)
+    }
+
+    /// Parses the sources one after the other, after expanding their
+    /// includes, macros and conditional blocks. Branch and jump delay slots are filled with
+    /// nops while in ".set reorder" mode, the default, or with the
+    /// previous instruction when optimizing and it is safe
+    pub fn from_sources(
+        sources: &[SourceFile],
+        options: &Options,
+    ) -> Result<Assembler, Vec<Diagnostic>> {
+        let mut assembler = Assembler::empty(options);
+        let mut diagnostics = Vec::new();
+        let mut reorder = true;
+        let mut segment = Segment::Text;
+
+        let (lines, constants) = preprocessor::expand(sources, options)?;
+        assembler.constants = constants;
+        assembler.small_data = small_data_labels(&lines);
+
+        for source_line in lines {
+            let line_number = source_line.line;
+            let mut text = source_line.text.trim();
+
+            // Labels point to the next instruction or data word,
+            // which can be on the same line or on a following one
+            if let Some(label) = text
+                .split_whitespace()
+                .next()
+                .filter(|word| Label::is_label(word))
+            {
+                let name = &label[..label.len() - 1];
+                let file = source_line.origin.file.as_deref();
+                if let Err(message) = assembler.define_label(name, segment, file) {
+                    diagnostics.push(source_line.origin.diagnostic(line_number, message));
                 }
-                InstructionFormat::J { op_code } => {
-                    let constant = words[1];
+                text = text[label.len()..].trim_start();
+            }
 
-                    if Label::is_label_reference(&constant) {
-                        let address = if instruction.name == "j" {
-                            true
-                        } else {
-                            false
-                        };
-                        let line = Label::find_label_line_address(&self.labels, &constant, address);
-
-                        // Conversion from a 32 bit integer to 26 bit integer
-                        let masked_number = line & 0x03FFFFFF;
-
-                        binary.push_str(&format!("{:06b}{:026b}", op_code, masked_number));
-                    } else {
-                        let masked_number = constant
-                            .parse::<i32>()
-                            .expect("Constante de 32 bits invalida")
-                            & ((1 << 26) - 1);
-                        binary.push_str(&format!("{:06b}{:026b}", op_code, masked_number));
+            let words: Vec<&str> = text.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let arguments = &text[words[0].len()..];
+
+            let result = match words[0] {
+                ".set" => match words.get(1) {
+                    Some(&"reorder") => {
+                        reorder = true;
+                        Ok(())
+                    }
+                    Some(&"noreorder") => {
+                        reorder = false;
+                        Ok(())
                     }
+                    _ => Err("Diretiva .set invalida".to_string()),
+                },
+                ".text" | ".data" | ".ktext" | ".kdata" | ".sdata" | ".sbss" => {
+                    segment = assembler.enter_section(words[0]);
+                    Ok(())
                 }
+                ".org" => assembler.org(arguments, segment),
+                ".align" => assembler.align(arguments, segment),
+                ".word" if segment == Segment::Data => {
+                    assembler.parse_words(arguments, &source_line)
+                }
+                ".word" => Err("Diretiva .word fora da secao .data".to_string()),
+                ".space" if segment == Segment::Data => assembler.space(arguments, &source_line),
+                ".space" => Err("Diretiva .space fora da secao .data".to_string()),
+                ".float" | ".double" if segment == Segment::Data => {
+                    assembler.parse_floats(words[0], arguments, &source_line)
+                }
+                ".float" | ".double" => Err(format!("Diretiva {} fora da secao .data", words[0])),
+                _ if segment == Segment::Data => Err("Instrucao fora da secao .text".to_string()),
+                _ => assembler.parse_statement(text, &source_line, reorder, options.optimize),
             };
 
-            file_out
-                .write(Assembler::to_hex(&binary, 0).as_bytes())
-                .expect("Erro ao escrever no arquivo de saida");
-            lines_written += 1;
+            if let Err(message) = result {
+                diagnostics.push(source_line.origin.diagnostic(line_number, message));
+            }
+        }
 
-            if lines_written % 4 == 0 {
-                file_out
-                    .write(b"\n")
-                    .expect("Erro ao escrever no arquivo de saida");
-                continue;
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        Ok(assembler)
+    }
+
+    pub(crate) fn empty(options: &Options) -> Assembler {
+        Assembler {
+            statements: Vec::new(),
+            data: Vec::new(),
+            labels: Vec::new(),
+            constants: Vec::new(),
+            layout: Layout::new(options.text_address, options.gp),
+            fill: options.fill,
+            small_data: Vec::new(),
+            emitters: Vec::new(),
+        }
+    }
+
+    /// Points a label to the next instruction or data word. Only
+    /// numeric labels can be defined more than once
+    pub(crate) fn define_label(
+        &mut self,
+        name: &str,
+        segment: Segment,
+        file: Option<&str>,
+    ) -> Result<(), String> {
+        if !Label::is_numeric(name) && Label::find(&self.labels, name, file).is_some() {
+            return Err(format!("Label duplicado: {}", name));
+        }
+        self.labels.push(Label {
+            name: name.to_string(),
+            file: file.map(str::to_string),
+            segment,
+            line: match segment {
+                Segment::Text => self.statements.len(),
+                Segment::Data => self.data.len(),
+            },
+        });
+        Ok(())
+    }
+
+    /// Switches to the section of a directive such as ".ktext",
+    /// returning the segment its contents go to
+    fn enter_section(&mut self, name: &str) -> Segment {
+        let section = layout::SECTIONS
+            .into_iter()
+            .find(|section| *section == name)
+            .expect("Secao desconhecida");
+        let segment = layout::segment(section);
+        self.layout.enter(section, self.segment_length(segment));
+        segment
+    }
+
+    /// Value of the argument of ".org" or ".align", which can use
+    /// constants and "." but no labels
+    fn directive_value(&self, argument: &str, segment: Segment) -> Result<i64, String> {
+        let context = Context {
+            labels: &[],
+            constants: &self.constants,
+            layout: &self.layout,
+            location: self.layout.address(segment, self.segment_length(segment)),
+            segment,
+            file: None,
+        };
+        Ok(Expression::parse(argument)?.evaluate(&context)?.value)
+    }
+
+    /// Places what follows in the current section at a fixed address
+    fn org(&mut self, argument: &str, segment: Segment) -> Result<(), String> {
+        let address = self.directive_value(argument, segment)?;
+        let address = u32::try_from(address)
+            .ok()
+            .filter(|address| address % 4 == 0)
+            .ok_or_else(|| format!("Endereco de .org invalido: {}", argument.trim()))?;
+        self.place(segment, Placement::At(address));
+        Ok(())
+    }
+
+    /// Places what follows in the current section at the next
+    /// multiple of 2^n bytes
+    fn align(&mut self, argument: &str, segment: Segment) -> Result<(), String> {
+        let power = self.directive_value(argument, segment)?;
+        if !(0..32).contains(&power) {
+            return Err(format!("Alinhamento invalido: {}", argument.trim()));
+        }
+        self.place(segment, Placement::Align(1 << power));
+        Ok(())
+    }
+
+    fn place(&mut self, segment: Segment, placement: Placement) {
+        let section = self
+            .layout
+            .current(segment)
+            .expect("Segmento sem chunk inicial")
+            .section;
+        let start = self.segment_length(segment);
+        self.layout.place(section, start, placement);
+    }
+
+    pub(crate) fn parse_statement(
+        &mut self,
+        text: &str,
+        source_line: &SourceLine,
+        reorder: bool,
+        optimize: bool,
+    ) -> Result<(), String> {
+        let expanded = pseudo::expand(text, source_line.line, &self.constants, &self.small_data)?;
+        if let Some(statements) = expanded {
+            let name = text.split_whitespace().next().unwrap_or(text);
+            delay_slot::check_expansion(&self.statements, name, statements.len())?;
+            for mut statement in statements {
+                statement.origin = source_line.origin.clone();
+                self.push_statement(statement, reorder, optimize)?;
             }
-            file_out
-                .write(b" ")
-                .expect("Erro ao escrever no arquivo de saida");
+            return Ok(());
         }
+
+        let mut statement = Statement::parse(text, source_line.line)?;
+        statement.origin = source_line.origin.clone();
+        self.push_statement(statement, reorder, optimize)
     }
 
-    fn to_hex(val: &str, len: usize) -> String {
-        // let n: i32 =
-        //     i32::from_str_radix(val, 2).expect("Erro ao converter binario para hexadecimal");
-        // format!("{:01$x}", n, len * 2)
-        // Parse the binary string as a u32 integer
-        println!("{}", val);
-        let decimal_value = u32::from_str_radix(val, 2);
+    /// Adds an instruction to the text segment, filling
+    /// its delay slot when it is a branch in reorder mode
+    pub(crate) fn push_statement(
+        &mut self,
+        statement: Statement,
+        reorder: bool,
+        optimize: bool,
+    ) -> Result<(), String> {
+        delay_slot::check_delay_slot(&self.statements, &statement)?;
 
-        // Check if the parsing was successful
-        match decimal_value {
-            Ok(value) => {
-                // Format the u32 integer as a hexadecimal string with a "0x" prefix
-                format!("0x{:08x}", value)
+        let is_branch_or_jump = statement.is_branch_or_jump();
+        self.statements.push(statement);
+        // The instruction before a new chunk is somewhere else in memory
+        let optimize = optimize
+            && !self
+                .layout
+                .starts_at(Segment::Text, self.statements.len() - 1);
+        if reorder && is_branch_or_jump {
+            delay_slot::fill_delay_slot(&mut self.statements, &self.labels, optimize);
+        }
+        self.layout.set_length(Segment::Text, self.statements.len());
+        Ok(())
+    }
+
+    /// Parses the values of a ".word" directive, which
+    /// are evaluated when the program is assembled
+    fn parse_words(&mut self, arguments: &str, source_line: &SourceLine) -> Result<(), String> {
+        if self.in_sbss() {
+            return Err("Diretiva .word na secao .sbss, que so aceita .space".to_string());
+        }
+        for value in statement::split_operands(arguments) {
+            if value.is_empty() {
+                return Err("Valor vazio em .word".to_string());
             }
-            Err(_) => {
-                // Handle parsing errors (invalid binary string)
-                "Invalid binary string".to_string()
+            let value = Expression::parse(&value)?;
+            self.push_word(value, source_line.line, source_line.origin.clone());
+        }
+        Ok(())
+    }
+
+    /// Parses the values of ".float" or ".double", stored in IEEE 754
+    /// single or double precision. A double takes two words, the upper
+    /// half first, and is aligned to 8 bytes like the registers it loads
+    pub(crate) fn parse_floats(
+        &mut self,
+        directive: &str,
+        arguments: &str,
+        source_line: &SourceLine,
+    ) -> Result<(), String> {
+        if self.in_sbss() {
+            return Err(format!(
+                "Diretiva {} na secao .sbss, que so aceita .space",
+                directive
+            ));
+        }
+        let values = statement::split_operands(arguments)
+            .iter()
+            .map(|value| {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| format!("Numero de ponto flutuante invalido: {}", value))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+
+        let mut words = Vec::new();
+        if directive == ".float" {
+            words.extend(values.iter().map(|value| (*value as f32).to_bits()));
+        } else {
+            let index = self.data.len();
+            if !values.is_empty() && !self.layout.address(Segment::Data, index).is_multiple_of(8) {
+                // Labels just defined point to the value, after the padding
+                for label in &mut self.labels {
+                    if label.segment == Segment::Data && label.line == index {
+                        label.line += 1;
+                    }
+                }
+                words.push(0);
+            }
+            for value in values {
+                let bits = value.to_bits();
+                words.push((bits >> 32) as u32);
+                words.push(bits as u32);
             }
         }
+
+        for word in words {
+            self.push_word(
+                Expression::Number(word as i64),
+                source_line.line,
+                source_line.origin.clone(),
+            );
+        }
+        Ok(())
     }
 
-    pub fn new(file_to_read: &str) -> Assembler {
-        Assembler {
-            file_to_read,
-            labels: Label::find_labels(file_to_read),
+    /// Reserves a number of bytes, a multiple of a word, set to zero
+    fn space(&mut self, arguments: &str, source_line: &SourceLine) -> Result<(), String> {
+        let size = self.directive_value(arguments, Segment::Data)?;
+        if size < 0 || size % 4 != 0 {
+            return Err(format!("Tamanho de .space invalido: {}", arguments.trim()));
+        }
+        for _ in 0..size / 4 {
+            self.push_word(
+                Expression::Number(0),
+                source_line.line,
+                source_line.origin.clone(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether data goes to ".sbss", which is only reserved with ".space"
+    fn in_sbss(&self) -> bool {
+        self.layout
+            .current(Segment::Data)
+            .is_some_and(|chunk| chunk.section == ".sbss")
+    }
+
+    pub(crate) fn push_word(&mut self, value: Expression, line: usize, origin: Origin) {
+        self.data.push(DataWord {
+            value,
+            line,
+            origin,
+        });
+        self.layout.set_length(Segment::Data, self.data.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assemble_str, Assembler, Options, Program, SourceFile};
+
+    fn assemble(source: &str) -> Program {
+        assemble_str(source, &Default::default()).unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        match assemble_str(source, &Default::default()) {
+            Ok(_) => panic!("Montou sem erros: {}", source),
+            Err(diagnostics) => diagnostics[0].message.clone(),
+        }
+    }
+
+    fn symbol(program: &Program, name: &str) -> u32 {
+        program
+            .symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .unwrap()
+            .address
+    }
+
+    fn words(program: &Program, section: &str) -> Vec<u32> {
+        program.section(section).unwrap().words.clone()
+    }
+
+    #[test]
+    fn finds_the_nearest_numeric_label() {
+        let program = assemble(".data\n1: .word 1b, 1f\n1: .word 1b");
+        assert_eq!(
+            words(&program, ".data"),
+            [0x1001_0000, 0x1001_0008, 0x1001_0008]
+        );
+
+        let program = assemble("beq $zero, $zero, 1f\n1: nop");
+        assert_eq!(words(&program, ".text"), [0x10000001, 0, 0]);
+        assert_eq!(
+            error("1: nop\nbeq $zero, $zero, 1f"),
+            "Label local nao encontrado: 1f"
+        );
+    }
+
+    #[test]
+    fn keeps_local_labels_in_their_file() {
+        let program = assemble(".Lloop: nop\nmain: nop\n1: nop");
+        let names: Vec<&str> = program.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["main"]);
+
+        let file = |name: &str, text: &str| SourceFile {
+            path: Some(name.to_string()),
+            text: text.to_string(),
+        };
+        let sources = [file("a.s", ".Lend: nop"), file("b.s", ".Lend: nop")];
+        assert!(Assembler::from_sources(&sources, &Default::default()).is_ok());
+
+        let sources = [file("a.s", ".Lend: nop"), file("b.s", ".data\n.word .Lend")];
+        let errors = Assembler::from_sources(&sources, &Default::default())
+            .unwrap()
+            .assemble()
+            .unwrap_err();
+        assert_eq!(errors[0].file.as_deref(), Some("b.s"));
+    }
+
+    #[test]
+    fn reports_duplicate_labels() {
+        let errors = assemble_str("x: nop\nx: nop", &Default::default()).unwrap_err();
+        assert_eq!(errors[0].message, "Label duplicado: x");
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(
+            error(".data\nx: .word 0\n.text\nx: nop"),
+            "Label duplicado: x"
+        );
+    }
+
+    #[test]
+    fn encodes_system_and_trap_instructions() {
+        let source = "\
+.set noreorder
+mfc0 $t0, $12
+mtc0 $t0, $14
+eret
+syscall
+break
+teq $t0, $t1
+tltu $t0, $t1
+tgei $t0, 5
+tnei $t0, -1";
+        assert_eq!(
+            words(&assemble(source), ".text"),
+            [
+                0x40086000, 0x40887000, 0x42000018, 0x0000000c, 0x0000000d, 0x01090034, 0x01090033,
+                0x05080005, 0x050effff,
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_floating_point_instructions() {
+        let source = "\
+.set noreorder
+add.s $f2, $f4, $f6
+sub.d $f2, $f4, $f6
+cvt.s.w $f0, $f2
+cvt.w.d $f0, $f2
+c.lt.s $f2, $f4
+c.eq.d $f2, $f4
+mtc1 $t0, $f0
+mfc1 $t0, $f1
+lwc1 $f0, 4($sp)
+sdc1 $f2, 8($sp)
+bc1t end
+nop
+end: nop";
+        assert_eq!(
+            words(&assemble(source), ".text"),
+            [
+                0x46062080, 0x46262081, 0x46801020, 0x46201024, 0x4604103c, 0x46241032, 0x44880000,
+                0x44080800, 0xc7a00004, 0xf7a20008, 0x45010001, 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn aligns_doubles_to_eight_bytes() {
+        let program = assemble(".data\n.float 1.5\nx: .double 2.0, -0.5");
+        assert_eq!(
+            words(&program, ".data"),
+            [0x3fc00000, 0, 0x40000000, 0, 0xbfe00000, 0]
+        );
+        assert_eq!(program.symbols[0].address, 0x1001_0008);
+    }
+
+    #[test]
+    fn encodes_release_2_instructions() {
+        let source = "\
+madd $t0, $t1
+msubu $t0, $t1
+clz $t0, $t1
+ext $t0, $t1, 4, 8
+ins $t0, $t1, 4, 8
+seb $t0, $t1
+seh $t0, $t1
+wsbh $t0, $t1
+rotr $t0, $t1, 3
+rotrv $t0, $t1, $t2";
+        assert_eq!(
+            words(&assemble(source), ".text"),
+            [
+                0x71090000, 0x71090005, 0x71284020, 0x7d283900, 0x7d285904, 0x7c094420, 0x7c094620,
+                0x7c0940a0, 0x002940c2, 0x01494046,
+            ]
+        );
+    }
+
+    #[test]
+    fn places_sbss_after_sdata() {
+        let program = assemble(".sbss\nx: .space 4\n.sdata\ny: .word 1\nz: .word 2");
+        assert_eq!(symbol(&program, "y"), 0x1000_0000);
+        assert_eq!(symbol(&program, "x"), 0x1000_0008);
+
+        let program = assemble(".sdata\ny: .word 1\n.sbss\nx: .space 4\n.sdata\nz: .word 2");
+        assert_eq!(symbol(&program, "z"), 0x1000_0004);
+        assert_eq!(symbol(&program, "x"), 0x1000_0008);
+    }
+
+    #[test]
+    fn checks_the_range_of_gp_rel() {
+        let source = ".sdata\nx: .word 1\n.text\nlw $t0, %gp_rel(x)($gp)";
+        assert_eq!(words(&assemble(source), ".text"), [0x8f888000]);
+
+        let source = ".data\nx: .word 1\n.text\nlw $t0, %gp_rel(x)($gp)";
+        assert_eq!(error(source), "Endereco fora do alcance de $gp: 0x10010000");
+
+        let options = Options {
+            gp: 0x1001_0000,
+            ..Default::default()
+        };
+        let program = assemble_str(source, &options).unwrap();
+        assert_eq!(words(&program, ".text"), [0x8f880000]);
+    }
+
+    #[test]
+    fn reports_overlapping_chunks() {
+        let source = "nop\nnop\n.org 0x00400004\nnop";
+        assert_eq!(error(source), "Sobreposicao com .text em 0x00400004");
+    }
+
+    #[test]
+    fn jumps_to_the_word_address_of_any_target() {
+        let source = "\
+nop
+1:
+foo: nop
+j foo
+j 1b
+j foo+0
+j . - 28
+jal foo
+j 0x100001";
+        let program = assemble(source);
+        let jumps: Vec<u32> = words(&program, ".text")
+            .into_iter()
+            .filter(|word| *word != 0)
+            .collect();
+        assert_eq!(
+            jumps,
+            [0x08100001, 0x08100001, 0x08100001, 0x08100001, 0x0c100001, 0x08100001]
+        );
+    }
+
+    #[test]
+    fn rejects_expansions_in_a_delay_slot() {
+        let source = ".set noreorder\nbeq $t0, $t1, end\nla $t2, end\nend: nop";
+        assert_eq!(
+            error(source),
+            "la se expande em 2 instrucoes no delay slot do desvio da linha 2"
+        );
+
+        // One instruction away from $gp fits the slot
+        let source = ".sdata\nx: .word 0\n.text\n.set noreorder\nj end\nlw $t2, x\nend: nop";
+        assert_eq!(
+            words(&assemble(source), ".text"),
+            [0x08100002, 0x8f8a8000, 0]
+        );
+    }
+
+    #[test]
+    fn checks_immediates_by_their_extension() {
+        let source = "\
+addi $t0, $zero, -32768
+slti $t0, $t1, 32767
+lw $t0, -4($sp)
+tgei $t0, -1
+ori $t0, $zero, 0xffff
+andi $t0, $t0, 0x8000
+lui $t0, 65535";
+        assert_eq!(
+            words(&assemble(source), ".text"),
+            [0x20088000, 0x29287fff, 0x8fa8fffc, 0x0508ffff, 0x3408ffff, 0x31088000, 0x3c08ffff,]
+        );
+
+        for (source, constant) in [
+            ("addi $t0, $zero, 40000", "40000"),
+            ("lw $t0, 40000($sp)", "40000"),
+            ("slti $t0, $t1, 0xffff", "0xffff"),
+            ("tnei $t0, 32768", "32768"),
+            ("ori $t0, $zero, -1", "-1"),
+            ("lui $t0, 0x10000", "0x10000"),
+        ] {
+            assert_eq!(
+                error(source),
+                format!("Constante de 16 bits invalida: {}", constant)
+            );
         }
     }
+
+    #[test]
+    fn encodes_the_code_of_syscall_and_break() {
+        let source = "syscall\nsyscall 10\nbreak 0xfffff\nbreak";
+        assert_eq!(
+            words(&assemble(source), ".text"),
+            [0x0000000c, 0x0000028c, 0x03ffffcd, 0x0000000d]
+        );
+        assert_eq!(
+            error("break 0x100000"),
+            "Codigo de 20 bits invalido: 0x100000"
+        );
+        assert_eq!(error("syscall -1"), "Codigo de 20 bits invalido: -1");
+    }
 }
@@ -0,0 +1,475 @@
+//! Typed API to generate programs from Rust code.
+//!
+//! ```
+//! use assembler::builder::Builder;
+//! use assembler::register::{T0, T1, T2, ZERO};
+//!
+//! let mut asm = Builder::new(Default::default());
+//! let done = asm.label("done");
+//! asm.add(T0, T1, T2);
+//! asm.beq(T0, ZERO, done);
+//! asm.addi(T0, T0, 1);
+//! asm.bind(done);
+//! let program = asm.finish().unwrap();
+//!
+//! let source = "add $t0, $t1, $t2\nbeq $t0, $zero, done\naddi $t0, $t0, 1\ndone:";
+//! assert_eq!(program, assembler::assemble_str(source, &Default::default()).unwrap());
+//! ```
+//!
+//! Instructions named with dots are methods with underscores, such as
+//! `add_s` for "add.s" and `c_eq_d` for "c.eq.d". The label forms of
+//! "lw" and "sw" are `lw_label` and `sw_label`, next to `la`.
+
+use crate::{
+    assembler::{Assembler, Options},
+    diagnostic::Diagnostic,
+    expression::Expression,
+    label::Segment,
+    preprocessor::{Origin, SourceLine},
+    program::Program,
+    register::{FloatRegister, Register},
+    statement::{Operand, Statement},
+};
+
+/// Position in the program, which can be used by
+/// branches and jumps before it is bound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Label(usize);
+
+/// Generates the methods of instructions sharing the same operands.
+/// Names with a dot, such as "add.s", are given in brackets after
+/// the method name. Immediates that are zero extended are `unsigned`
+macro_rules! instructions {
+    ($(fn $name:ident$([$instruction:literal])?($($operand:ident: $kind:ident),*);)*) => {
+        $(
+            pub fn $name(&mut self, $($operand: instructions!(@type $kind)),*) -> &mut Self {
+                let operands = vec![$(instructions!(@operand self, $kind, $operand)),*];
+                self.push(instructions!(@name $name $($instruction)?), operands)
+            }
+        )*
+    };
+    (@name $name:ident) => { stringify!($name) };
+    (@name $name:ident $instruction:literal) => { $instruction };
+    (@type register) => { Register };
+    (@type float) => { FloatRegister };
+    (@type constant) => { i16 };
+    (@type unsigned) => { u16 };
+    (@type label) => { Label };
+    (@operand $self:ident, register, $value:ident) => { Operand::Register($value.number) };
+    (@operand $self:ident, float, $value:ident) => { Operand::FloatRegister($value.number) };
+    (@operand $self:ident, constant, $value:ident) => { Operand::Constant($value.to_string()) };
+    (@operand $self:ident, unsigned, $value:ident) => { Operand::Constant($value.to_string()) };
+    (@operand $self:ident, label, $value:ident) => { Operand::Constant($self.label_name($value)) };
+}
+
+/// Builds a program one instruction at a time. The result is the
+/// same program the text assembler produces for the equivalent
+/// source, delay slots included. Diagnostics point to the position
+/// of the call that emitted the instruction, starting at 1
+pub struct Builder {
+    assembler: Assembler,
+    options: Options,
+    /// Name of each label and whether it is bound
+    labels: Vec<(String, bool)>,
+    diagnostics: Vec<Diagnostic>,
+    segment: Segment,
+    reorder: bool,
+    calls: usize,
+}
+
+impl Builder {
+    pub fn new(options: Options) -> Builder {
+        Builder {
+            assembler: Assembler::empty(&options),
+            options,
+            labels: Vec::new(),
+            diagnostics: Vec::new(),
+            segment: Segment::Text,
+            reorder: true,
+            calls: 0,
+        }
+    }
+
+    /// Creates a label that shows up in the program symbols
+    pub fn label(&mut self, name: &str) -> Label {
+        self.labels.push((name.to_string(), false));
+        Label(self.labels.len() - 1)
+    }
+
+    /// Creates an unnamed label
+    pub fn new_label(&mut self) -> Label {
+        let name = format!(".L{}", self.labels.len());
+        self.label(&name)
+    }
+
+    /// Points the label to the next instruction or data word.
+    /// A label can only be bound once
+    pub fn bind(&mut self, label: Label) -> &mut Self {
+        let (name, bound) = &mut self.labels[label.0];
+        assert!(!*bound, "Label ja definido: {}", name);
+        *bound = true;
+
+        let name = name.clone();
+        if let Err(message) = self.assembler.define_label(&name, self.segment, None) {
+            self.diagnostics.push(Diagnostic::new(self.calls, message));
+        }
+        self
+    }
+
+    fn label_name(&self, label: Label) -> String {
+        self.labels[label.0].0.clone()
+    }
+
+    /// Same as ".set reorder" and ".set noreorder"
+    pub fn set_reorder(&mut self, reorder: bool) -> &mut Self {
+        self.reorder = reorder;
+        self
+    }
+
+    /// Same as ".text"
+    pub fn text(&mut self) -> &mut Self {
+        self.segment = Segment::Text;
+        self
+    }
+
+    /// Same as ".data"
+    pub fn data(&mut self) -> &mut Self {
+        self.segment = Segment::Data;
+        self
+    }
+
+    /// Same as ".word", only valid in the data segment
+    pub fn word(&mut self, value: u32) -> &mut Self {
+        self.calls += 1;
+        match self.segment {
+            Segment::Data => self.assembler.push_word(
+                Expression::Number(value as i64),
+                self.calls,
+                Origin::default(),
+            ),
+            Segment::Text => self.diagnostics.push(Diagnostic::new(
+                self.calls,
+                "Diretiva .word fora da secao .data".to_string(),
+            )),
+        }
+        self
+    }
+
+    /// Same as ".float", only valid in the data segment
+    pub fn float(&mut self, value: f32) -> &mut Self {
+        self.push_floats(".float", value.to_string())
+    }
+
+    /// Same as ".double", only valid in the data segment. The value
+    /// is aligned to 8 bytes, as in a source
+    pub fn double(&mut self, value: f64) -> &mut Self {
+        self.push_floats(".double", value.to_string())
+    }
+
+    fn push_floats(&mut self, directive: &str, value: String) -> &mut Self {
+        self.calls += 1;
+        let source_line = SourceLine {
+            text: value,
+            line: self.calls,
+            origin: Origin::default(),
+        };
+        let result = match self.segment {
+            Segment::Data => {
+                self.assembler
+                    .parse_floats(directive, &source_line.text, &source_line)
+            }
+            Segment::Text => Err(format!("Diretiva {} fora da secao .data", directive)),
+        };
+        if let Err(message) = result {
+            self.diagnostics.push(Diagnostic::new(self.calls, message));
+        }
+        self
+    }
+
+    fn push(&mut self, name: &str, operands: Vec<Operand>) -> &mut Self {
+        let statement = Statement::new(name, operands, self.calls + 1);
+        self.push_with(|assembler, reorder, optimize| {
+            assembler.push_statement(statement, reorder, optimize)
+        })
+    }
+
+    /// Adds an instruction written as in a source, which may
+    /// expand to more than one machine instruction
+    fn push_expanded(&mut self, text: String) -> &mut Self {
+        let source_line = SourceLine {
+            text,
+            line: self.calls + 1,
+            origin: Origin::default(),
+        };
+        self.push_with(|assembler, reorder, optimize| {
+            assembler.parse_statement(&source_line.text, &source_line, reorder, optimize)
+        })
+    }
+
+    fn push_with(
+        &mut self,
+        push: impl FnOnce(&mut Assembler, bool, bool) -> Result<(), String>,
+    ) -> &mut Self {
+        self.calls += 1;
+        let result = match self.segment {
+            Segment::Text => push(&mut self.assembler, self.reorder, self.options.optimize),
+            Segment::Data => Err("Instrucao fora da secao .text".to_string()),
+        };
+
+        if let Err(message) = result {
+            self.diagnostics.push(Diagnostic::new(self.calls, message));
+        }
+        self
+    }
+
+    instructions! {
+        fn nop();
+        fn sll(rd: register, rt: register, shamt: constant);
+        fn srl(rd: register, rt: register, shamt: constant);
+        fn jr(rs: register);
+        fn mfhi(rd: register);
+        fn mflo(rd: register);
+        fn mult(rs: register, rt: register);
+        fn multu(rs: register, rt: register);
+        fn div(rs: register, rt: register);
+        fn divu(rs: register, rt: register);
+        fn add(rd: register, rs: register, rt: register);
+        fn addu(rd: register, rs: register, rt: register);
+        fn sub(rd: register, rs: register, rt: register);
+        fn subu(rd: register, rs: register, rt: register);
+        fn and(rd: register, rs: register, rt: register);
+        fn or(rd: register, rs: register, rt: register);
+        fn slt(rd: register, rs: register, rt: register);
+        fn sltu(rd: register, rs: register, rt: register);
+        fn mul(rd: register, rs: register, rt: register);
+        fn madd(rs: register, rt: register);
+        fn maddu(rs: register, rt: register);
+        fn msub(rs: register, rt: register);
+        fn msubu(rs: register, rt: register);
+        fn clz(rd: register, rs: register);
+        fn clo(rd: register, rs: register);
+        fn ext(rt: register, rs: register, pos: constant, size: constant);
+        fn ins(rt: register, rs: register, pos: constant, size: constant);
+        fn seb(rd: register, rt: register);
+        fn seh(rd: register, rt: register);
+        fn wsbh(rd: register, rt: register);
+        fn rotr(rd: register, rt: register, shamt: constant);
+        fn rotrv(rd: register, rt: register, rs: register);
+        fn beq(rs: register, rt: register, target: label);
+        fn bne(rs: register, rt: register, target: label);
+        fn addi(rt: register, rs: register, immediate: constant);
+        fn addiu(rt: register, rs: register, immediate: constant);
+        fn slti(rt: register, rs: register, immediate: constant);
+        fn sltiu(rt: register, rs: register, immediate: constant);
+        fn andi(rt: register, rs: register, immediate: unsigned);
+        fn ori(rt: register, rs: register, immediate: unsigned);
+        fn lui(rt: register, immediate: unsigned);
+        fn j(target: label);
+        fn jal(target: label);
+        fn tge(rs: register, rt: register);
+        fn tgeu(rs: register, rt: register);
+        fn tlt(rs: register, rt: register);
+        fn tltu(rs: register, rt: register);
+        fn teq(rs: register, rt: register);
+        fn tne(rs: register, rt: register);
+        fn tgei(rs: register, immediate: constant);
+        fn tgeiu(rs: register, immediate: constant);
+        fn tlti(rs: register, immediate: constant);
+        fn tltiu(rs: register, immediate: constant);
+        fn teqi(rs: register, immediate: constant);
+        fn tnei(rs: register, immediate: constant);
+        fn mfc0(rt: register, rd: register);
+        fn mtc0(rt: register, rd: register);
+        fn eret();
+        fn add_s["add.s"](fd: float, fs: float, ft: float);
+        fn add_d["add.d"](fd: float, fs: float, ft: float);
+        fn sub_s["sub.s"](fd: float, fs: float, ft: float);
+        fn sub_d["sub.d"](fd: float, fs: float, ft: float);
+        fn mul_s["mul.s"](fd: float, fs: float, ft: float);
+        fn mul_d["mul.d"](fd: float, fs: float, ft: float);
+        fn div_s["div.s"](fd: float, fs: float, ft: float);
+        fn div_d["div.d"](fd: float, fs: float, ft: float);
+        fn sqrt_s["sqrt.s"](fd: float, fs: float);
+        fn sqrt_d["sqrt.d"](fd: float, fs: float);
+        fn abs_s["abs.s"](fd: float, fs: float);
+        fn abs_d["abs.d"](fd: float, fs: float);
+        fn mov_s["mov.s"](fd: float, fs: float);
+        fn mov_d["mov.d"](fd: float, fs: float);
+        fn neg_s["neg.s"](fd: float, fs: float);
+        fn neg_d["neg.d"](fd: float, fs: float);
+        fn cvt_s_d["cvt.s.d"](fd: float, fs: float);
+        fn cvt_s_w["cvt.s.w"](fd: float, fs: float);
+        fn cvt_d_s["cvt.d.s"](fd: float, fs: float);
+        fn cvt_d_w["cvt.d.w"](fd: float, fs: float);
+        fn cvt_w_s["cvt.w.s"](fd: float, fs: float);
+        fn cvt_w_d["cvt.w.d"](fd: float, fs: float);
+        fn c_eq_s["c.eq.s"](fs: float, ft: float);
+        fn c_eq_d["c.eq.d"](fs: float, ft: float);
+        fn c_lt_s["c.lt.s"](fs: float, ft: float);
+        fn c_lt_d["c.lt.d"](fs: float, ft: float);
+        fn c_le_s["c.le.s"](fs: float, ft: float);
+        fn c_le_d["c.le.d"](fs: float, ft: float);
+        fn mfc1(rt: register, fs: float);
+        fn mtc1(rt: register, fs: float);
+        fn bc1t(target: label);
+        fn bc1f(target: label);
+    }
+
+    /// "syscall", with code 0
+    pub fn syscall(&mut self) -> &mut Self {
+        self.push("syscall", vec![Operand::Constant("0".to_string())])
+    }
+
+    /// "break", which is a keyword in Rust, with code 0
+    pub fn break_(&mut self) -> &mut Self {
+        self.push("break", vec![Operand::Constant("0".to_string())])
+    }
+
+    /// "lw $rt, offset($base)"
+    pub fn lw(&mut self, rt: Register, offset: i16, base: Register) -> &mut Self {
+        self.push("lw", Builder::memory(rt, offset, base))
+    }
+
+    /// "sw $rt, offset($base)"
+    pub fn sw(&mut self, rt: Register, offset: i16, base: Register) -> &mut Self {
+        self.push("sw", Builder::memory(rt, offset, base))
+    }
+
+    fn memory(rt: Register, offset: i16, base: Register) -> Vec<Operand> {
+        vec![
+            Operand::Register(rt.number),
+            Operand::Memory {
+                offset: offset.to_string(),
+                base: base.number,
+            },
+        ]
+    }
+
+    /// "lwc1 $ft, offset($base)"
+    pub fn lwc1(&mut self, ft: FloatRegister, offset: i16, base: Register) -> &mut Self {
+        self.push("lwc1", Builder::float_memory(ft, offset, base))
+    }
+
+    /// "swc1 $ft, offset($base)"
+    pub fn swc1(&mut self, ft: FloatRegister, offset: i16, base: Register) -> &mut Self {
+        self.push("swc1", Builder::float_memory(ft, offset, base))
+    }
+
+    /// "ldc1 $ft, offset($base)"
+    pub fn ldc1(&mut self, ft: FloatRegister, offset: i16, base: Register) -> &mut Self {
+        self.push("ldc1", Builder::float_memory(ft, offset, base))
+    }
+
+    /// "sdc1 $ft, offset($base)"
+    pub fn sdc1(&mut self, ft: FloatRegister, offset: i16, base: Register) -> &mut Self {
+        self.push("sdc1", Builder::float_memory(ft, offset, base))
+    }
+
+    fn float_memory(ft: FloatRegister, offset: i16, base: Register) -> Vec<Operand> {
+        vec![
+            Operand::FloatRegister(ft.number),
+            Operand::Memory {
+                offset: offset.to_string(),
+                base: base.number,
+            },
+        ]
+    }
+
+    /// "la $rd, label", expanded as in a source
+    pub fn la(&mut self, rd: Register, label: Label) -> &mut Self {
+        let text = format!("la ${}, {}", rd.name, self.label_name(label));
+        self.push_expanded(text)
+    }
+
+    /// "lw $rt, label", expanded as in a source
+    pub fn lw_label(&mut self, rt: Register, label: Label) -> &mut Self {
+        let text = format!("lw ${}, {}", rt.name, self.label_name(label));
+        self.push_expanded(text)
+    }
+
+    /// "sw $rt, label", expanded as in a source
+    pub fn sw_label(&mut self, rt: Register, label: Label) -> &mut Self {
+        let text = format!("sw ${}, {}", rt.name, self.label_name(label));
+        self.push_expanded(text)
+    }
+
+    /// Resolves every label and encodes the program
+    pub fn finish(mut self) -> Result<Program, Vec<Diagnostic>> {
+        if let Some(config) = &self.options.insert_nops {
+            self.assembler.check_hazards(config, true);
+        }
+
+        let mut diagnostics = self.diagnostics;
+        match self.assembler.assemble() {
+            Ok(program) if diagnostics.is_empty() => Ok(program),
+            Ok(_) => Err(diagnostics),
+            Err(errors) => {
+                diagnostics.extend(errors);
+                Err(diagnostics)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_str;
+    use crate::register::*;
+
+    #[test]
+    fn builds_the_program_of_the_equivalent_source() {
+        let mut asm = Builder::new(Default::default());
+        let x = asm.label("x");
+        let d = asm.label("d");
+        let l = asm.label("l");
+        asm.ori(T0, T0, 0xffff).lui(T1, 0x8000).andi(T2, T2, 0xf0f0);
+        asm.la(T3, x).lw_label(T4, x).sw_label(T4, d);
+        asm.bind(l);
+        asm.add_s(F0, F1, F2).c_eq_d(F2, F4).bc1t(l).mtc1(T0, F3);
+        asm.lwc1(F0, 4, SP).ldc1(F2, 0, T3);
+        asm.data().bind(x).word(1).float(1.5).bind(d).double(3.25);
+        let program = asm.finish().unwrap();
+
+        let source = "\
+ori $t0, $t0, 0xffff
+lui $t1, 0x8000
+andi $t2, $t2, 0xf0f0
+la $t3, x
+lw $t4, x
+sw $t4, d
+l: add.s $f0, $f1, $f2
+c.eq.d $f2, $f4
+bc1t l
+mtc1 $t0, $f3
+lwc1 $f0, 4($sp)
+ldc1 $f2, 0($t3)
+.data
+x: .word 1
+.float 1.5
+d: .double 3.25";
+        assert_eq!(program, assemble_str(source, &Default::default()).unwrap());
+    }
+
+    #[test]
+    fn reports_the_call_of_each_error() {
+        let mut asm = Builder::new(Default::default());
+        let end = asm.new_label();
+        asm.add_d(F1, F2, F4).data().float(1.0).text().double(1.0);
+        asm.beq(T0, ZERO, end);
+        let messages: Vec<String> = asm
+            .finish()
+            .unwrap_err()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "linha 3: Diretiva .double fora da secao .data",
+                "linha 1: Registrador de precisao dupla deve ser par: $f1",
+                "linha 4: Simbolo nao encontrado: .L0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use assembler::{emitter, hazard::PipelineConfig, Options};
+
+pub const HELP: &str = "\
+Montador MIPS para memorias do Logisim
+
+Uso:
+    assembler [asm] <entradas...> [opcoes]
+    assembler disasm <entrada> [-o <saida>]
+    assembler check <entradas...> [opcoes]
+    assembler fmt <entradas...>
+
+Comandos:
+    asm       monta as entradas, na ordem dadas (padrao)
+    disasm    converte uma imagem do Logisim ou palavras em hexadecimal
+              de volta para assembly
+    check     monta as entradas e so reporta os erros
+    fmt       reescreve as entradas com um layout padrao
+
+Use - como entrada ou saida para ler de stdin ou escrever em stdout.
+
+O alvo de j e jal e o campo de 26 bits da instrucao. Labels e enderecos,
+como . ou foo+4, sao convertidos para ele (endereco >> 2), mas um numero,
+como j 0x100000, ja e o proprio campo, como o disasm escreve.
+
+Opcoes:
+    -o <saida>[:formato]  arquivo de saida (padrao: out.bin ou stdout).
+                          Pode ser repetido para gerar varios formatos
+                          de uma vez, como -o rom.hex:logisim -o rom.mif:mif
+    --format <formato>    formato das saidas sem um: logisim (padrao), hex,
+                          bin, mif, listing ou symbols
+    --base <endereco>     endereco da primeira instrucao (padrao: 0x00400000)
+    --gp <endereco>       valor de $gp, de onde .sdata e .sbss sao
+                          acessados (padrao: 0x10008000). O programa
+                          deve carregar $gp com ele
+    --fill <palavra>      palavra nos enderecos sem instrucao da imagem,
+                          como antes de um .org (padrao: 0)
+    -I <diretorio>        procura arquivos de .include tambem neste
+                          diretorio, depois do diretorio de quem inclui
+    -D <nome>[=valor]     define uma constante, como .eqv (padrao: 1)
+    -O                    preenche delay slots com a instrucao anterior
+                          quando for seguro
+    --hazards             reporta hazards do pipeline abaixo, tambem nos
+                          registradores $f. Operacoes de ponto flutuante
+                          levam um ciclo, como as da ULA
+    --insert-nops         insere os nops que removem os hazards
+    --no-forwarding       pipeline sem forwarding
+    --load-use <n>        instrucoes entre um load e seu uso
+    --branch-delay <n>    instrucoes buscadas antes do desvio ser resolvido
+    -h, --help            mostra esta ajuda
+
+Numeros nas opcoes sao escritos como no codigo: 10, -1, 0x0a, 0b1010,
+0o12 ou 1_000.
+
+Codigos de saida:
+    0    sucesso
+    1    erros no assembly
+    2    uso invalido
+    3    erro ao ler ou escrever arquivos
+";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Asm,
+    Disasm,
+    Check,
+    Fmt,
+}
+
+/// File given with -o and the format written to it
+pub struct Output {
+    pub path: String,
+    pub format: String,
+}
+
+pub struct Args {
+    pub command: Command,
+    pub help: bool,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<Output>,
+    pub format: String,
+    pub options: Options,
+    pub pipeline: PipelineConfig,
+    pub check_hazards: bool,
+    pub insert_nops: bool,
+}
+
+/// Parses the command line, without the program name
+pub fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("asm") => Some(Command::Asm),
+        Some("disasm") => Some(Command::Disasm),
+        Some("check") => Some(Command::Check),
+        Some("fmt") => Some(Command::Fmt),
+        _ => None,
+    };
+    if command.is_some() {
+        args.next();
+    }
+
+    let mut parsed = Args {
+        // Without a command, the arguments are inputs to "asm"
+        command: command.unwrap_or(Command::Asm),
+        help: false,
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        format: "logisim".to_string(),
+        options: Options::default(),
+        pipeline: PipelineConfig::default(),
+        check_hazards: false,
+        insert_nops: false,
+    };
+
+    let mut outputs = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("Valor faltando para {}", name))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => parsed.help = true,
+            "-o" => outputs.push(value("-o")?),
+            "--format" => parsed.format = parse_format(&value("--format")?)?,
+            "--base" => parsed.options.text_address = parse_address(&value("--base")?)?,
+            "--gp" => parsed.options.gp = parse_address(&value("--gp")?)?,
+            "--fill" => parsed.options.fill = parse_word(&value("--fill")?)?,
+            "-I" => parsed.options.include_paths.push(value("-I")?.into()),
+            _ if arg.starts_with("-I") => parsed.options.include_paths.push(arg[2..].into()),
+            "-D" => parsed.options.defines.push(parse_define(&value("-D")?)?),
+            _ if arg.starts_with("-D") => parsed.options.defines.push(parse_define(&arg[2..])?),
+            "-O" => parsed.options.optimize = true,
+            "--hazards" => parsed.check_hazards = true,
+            "--insert-nops" => {
+                parsed.insert_nops = true;
+                parsed.check_hazards = true;
+            }
+            "--no-forwarding" => {
+                parsed.pipeline.forwarding = false;
+                parsed.check_hazards = true;
+            }
+            "--load-use" => {
+                let distance = value("--load-use")?;
+                parsed.pipeline.load_use_distance = parse_count(&distance)
+                    .ok_or_else(|| format!("Distancia invalida: {}", distance))?;
+                parsed.check_hazards = true;
+            }
+            "--branch-delay" => {
+                let delay = value("--branch-delay")?;
+                parsed.pipeline.branch_delay = parse_count(&delay)
+                    .ok_or_else(|| format!("Atraso de desvio invalido: {}", delay))?;
+                parsed.check_hazards = true;
+            }
+            "-" => parsed.inputs.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("Argumento invalido: {}", arg)),
+            _ => parsed.inputs.push(arg),
+        }
+    }
+
+    if parsed.help {
+        return Ok(parsed);
+    }
+    if parsed.inputs.is_empty() {
+        return Err("Nenhuma entrada".to_string());
+    }
+    if parsed.command == Command::Disasm && parsed.inputs.len() > 1 {
+        return Err("disasm aceita uma entrada".to_string());
+    }
+    if parsed.command == Command::Disasm && outputs.len() > 1 {
+        return Err("disasm aceita uma saida".to_string());
+    }
+
+    for output in outputs {
+        parsed.outputs.push(parse_output(&output, &parsed.format));
+    }
+
+    Ok(parsed)
+}
+
+/// Parses "NAME=value", where the value is 1 when left out
+fn parse_define(define: &str) -> Result<(String, i64), String> {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("Nome de constante invalido: {}", name));
+    }
+
+    match assembler::parse_literal(value) {
+        Some(parsed) => Ok((name.to_string(), parsed)),
+        None => Err(format!("Valor invalido para {}: {}", name, value)),
+    }
+}
+
+fn parse_address(address: &str) -> Result<u32, String> {
+    assembler::parse_literal(address)
+        .and_then(|parsed| u32::try_from(parsed).ok())
+        .filter(|parsed| parsed % 4 == 0)
+        .ok_or_else(|| format!("Endereco invalido: {}", address))
+}
+
+/// 32 bit word, where negative values keep their two's complement bits
+fn parse_word(word: &str) -> Result<u32, String> {
+    assembler::parse_literal(word)
+        .filter(|parsed| (i32::MIN as i64..=u32::MAX as i64).contains(parsed))
+        .map(|parsed| parsed as u32)
+        .ok_or_else(|| format!("Palavra invalida: {}", word))
+}
+
+/// Number of instructions, which can not be negative
+fn parse_count(count: &str) -> Option<usize> {
+    assembler::parse_literal(count).and_then(|parsed| usize::try_from(parsed).ok())
+}
+
+fn parse_format(format: &str) -> Result<String, String> {
+    if emitter::NAMES.contains(&format) {
+        Ok(format.to_string())
+    } else {
+        Err(format!("Formato invalido: {}", format))
+    }
+}
+
+/// Splits "path:format", where the format is optional. The suffix
+/// is only taken as a format when it names one, so paths with ':'
+/// are kept whole
+fn parse_output(output: &str, default_format: &str) -> Output {
+    match output.rsplit_once(':') {
+        Some((path, format)) if !path.is_empty() && emitter::NAMES.contains(&format) => Output {
+            path: path.to_string(),
+            format: format.to_string(),
+        },
+        _ => Output {
+            path: output.to_string(),
+            format: default_format.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        parse(line.split_whitespace().map(str::to_string))
+    }
+
+    fn error(line: &str) -> String {
+        args(line).err().unwrap()
+    }
+
+    #[test]
+    fn reads_commands_inputs_and_outputs() {
+        let parsed = args("a.s b.s -o rom.hex:hex -o c:\\rom --format mif").unwrap();
+        assert_eq!(parsed.command, Command::Asm);
+        assert_eq!(parsed.inputs, ["a.s", "b.s"]);
+        let outputs: Vec<(&str, &str)> = parsed
+            .outputs
+            .iter()
+            .map(|output| (output.path.as_str(), output.format.as_str()))
+            .collect();
+        assert_eq!(outputs, [("rom.hex", "hex"), ("c:\\rom", "mif")]);
+
+        assert_eq!(args("disasm -").unwrap().command, Command::Disasm);
+        assert!(args("-h").unwrap().help);
+        assert_eq!(error("disasm a b"), "disasm aceita uma entrada");
+        assert_eq!(error(""), "Nenhuma entrada");
+        assert_eq!(error("a.s --verbose"), "Argumento invalido: --verbose");
+        assert_eq!(error("a.s --format elf"), "Formato invalido: elf");
+        assert_eq!(error("a.s -o"), "Valor faltando para -o");
+    }
+
+    #[test]
+    fn reads_numbers_as_the_source_does() {
+        let parsed =
+            args("a.s --base 0x40_0000 --gp 0x10008000 --fill -1 -DFAST -D N=0b101 -I inc")
+                .unwrap();
+        assert_eq!(parsed.options.text_address, 0x0040_0000);
+        assert_eq!(parsed.options.gp, 0x1000_8000);
+        assert_eq!(parsed.options.fill, u32::MAX);
+        assert_eq!(
+            parsed.options.defines,
+            [("FAST".to_string(), 1), ("N".to_string(), 5)]
+        );
+        assert_eq!(
+            parsed.options.include_paths,
+            [std::path::PathBuf::from("inc")]
+        );
+
+        let parsed = args("a.s --load-use 0x2 --branch-delay 0b1").unwrap();
+        assert_eq!(parsed.pipeline.load_use_distance, 2);
+        assert_eq!(parsed.pipeline.branch_delay, 1);
+        assert!(parsed.check_hazards);
+
+        assert_eq!(error("a.s --base 2"), "Endereco invalido: 2");
+        assert_eq!(error("a.s -D 1X"), "Nome de constante invalido: 1X");
+        assert_eq!(error("a.s --load-use -1"), "Distancia invalida: -1");
+        assert_eq!(
+            error("a.s --branch-delay x"),
+            "Atraso de desvio invalido: x"
+        );
+    }
+}
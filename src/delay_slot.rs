@@ -0,0 +1,94 @@
+use crate::{
+    label::{Label, Segment},
+    statement::Statement,
+};
+
+/// Whether the last statement sits in the delay slot of a branch or jump
+fn in_delay_slot(statements: &[Statement]) -> bool {
+    statements.len() >= 2 && statements[statements.len() - 2].is_branch_or_jump()
+}
+
+/// Fails if `statement` would be placed in the delay
+/// slot of the branch or jump before it
+pub fn check_delay_slot(statements: &[Statement], statement: &Statement) -> Result<(), String> {
+    if !statement.is_branch_or_jump() {
+        return Ok(());
+    }
+    match statements.last() {
+        Some(previous) if previous.is_branch_or_jump() => Err(format!(
+            "Desvio no delay slot do desvio da linha {}",
+            previous.line
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Fails if an instruction that expands to `count` machine instructions
+/// would start in the delay slot of the branch or jump before it, where
+/// only the first one runs before the branch is taken
+pub fn check_expansion(statements: &[Statement], name: &str, count: usize) -> Result<(), String> {
+    match statements.last() {
+        Some(previous) if count > 1 && previous.is_branch_or_jump() => Err(format!(
+            "{} se expande em {} instrucoes no delay slot do desvio da linha {}",
+            name, count, previous.line
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the instruction before the branch that was just added
+/// can be moved into its delay slot without changing the program
+fn can_fill_with_previous(statements: &[Statement], labels: &[Label]) -> bool {
+    let branch_index = statements.len() - 1;
+    if branch_index == 0 || in_delay_slot(&statements[..branch_index]) {
+        return false;
+    }
+
+    let branch = &statements[branch_index];
+    let candidate = &statements[branch_index - 1];
+
+    // Jumping to the branch label would skip the moved instruction
+    if labels
+        .iter()
+        .any(|label| label.segment == Segment::Text && label.line == branch_index)
+    {
+        return false;
+    }
+
+    // Branches on the coprocessor 1 flag depend on the compare setting it
+    if branch.instruction.name.starts_with("bc1") && candidate.instruction.name.starts_with("c.") {
+        return false;
+    }
+
+    // The branch must not depend on the moved instruction, and the moved
+    // instruction must not see or overwrite what the branch writes ($ra)
+    if let Some(register) = candidate.written_register() {
+        if branch.read_registers().contains(&register) {
+            return false;
+        }
+    }
+    if let Some(register) = branch.written_register() {
+        if candidate.written_register() == Some(register)
+            || candidate.read_registers().contains(&register)
+        {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Fills the delay slot of the branch or jump that was just added,
+/// as done in ".set reorder" mode. A nop is used unless `optimize`
+/// is set and the previous instruction can safely take its place
+pub fn fill_delay_slot(statements: &mut Vec<Statement>, labels: &[Label], optimize: bool) {
+    let branch_index = statements.len() - 1;
+
+    if optimize && can_fill_with_previous(statements, labels) {
+        statements.swap(branch_index - 1, branch_index);
+        return;
+    }
+
+    let line = statements[branch_index].line;
+    statements.push(Statement::nop(line));
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+
+/// Error found while assembling, pointing
+/// to the source line that caused it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// File the line is in, None for a source given in memory
+    pub file: Option<String>,
+    /// Source line, starting at 1. Inside a macro,
+    /// this is the line in the macro definition
+    pub line: usize,
+    pub message: String,
+    /// Macro calls the line was expanded from, innermost first
+    pub expansions: Vec<Expansion>,
+    /// ".include" directives that led to the file, innermost first
+    pub includes: Vec<Location>,
+}
+
+/// Line in a source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// None for a source given in memory
+    pub file: Option<String>,
+    /// Starting at 1
+    pub line: usize,
+}
+
+/// Call to a macro, as seen from the lines it expanded to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    /// Line of the ".macro" directive
+    pub definition: Location,
+    /// Line of the call, which is in another macro
+    /// when it is not the last expansion
+    pub call: Location,
+}
+
+impl Diagnostic {
+    /// Diagnostic for a line of a source given in memory
+    pub fn new(line: usize, message: String) -> Diagnostic {
+        Diagnostic {
+            file: None,
+            line,
+            message,
+            expansions: Vec::new(),
+            includes: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = Location {
+            file: self.file.clone(),
+            line: self.line,
+        };
+        write!(f, "{}: {}", location, self.message)?;
+        for expansion in &self.expansions {
+            write!(f, "\n    {}", expansion)?;
+        }
+        for include in &self.includes {
+            write!(f, "\n    incluido em {}", include)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}: linha {}", file, self.line),
+            None => write!(f, "linha {}", self.line),
+        }
+    }
+}
+
+impl fmt::Display for Expansion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "na macro {} ({}), chamada em {}",
+            self.name, self.definition, self.call
+        )
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    instruction::{Instruction, INSTRUCTIONS},
+    instruction_format::{InstructionFormat, BSHFL, COP0, COP1, REGIMM, SPECIAL3},
+    register::Register,
+};
+
+fn find_instruction(word: u32) -> Option<&'static Instruction> {
+    let op_code = (word >> 26) as u8;
+    let function = (word & 0x3f) as u8;
+    let (rs, rt) = ((word >> 21 & 0x1f) as u8, (word >> 16 & 0x1f) as u8);
+    let shamt = (word >> 6 & 0x1f) as u8;
+
+    INSTRUCTIONS.iter().find(|instruction| {
+        // Every word with a zero op_code and function would match "nop"
+        if instruction.name == "nop" {
+            return word == 0;
+        }
+        match instruction.format {
+            // srl with a 1 in rs is rotr
+            InstructionFormat::R {
+                op_code: r_op_code,
+                function: r_function,
+            } => {
+                r_op_code == op_code
+                    && r_function == function
+                    && (instruction.name != "srl" || rs == 0)
+            }
+            InstructionFormat::I { op_code: i_op_code } => i_op_code == op_code,
+            InstructionFormat::J { op_code: j_op_code } => j_op_code == op_code,
+            InstructionFormat::RegImm { rt: regimm_rt } => op_code == REGIMM && regimm_rt == rt,
+            InstructionFormat::Cop0 {
+                rs: cop0_rs,
+                function: cop0_function,
+            } => op_code == COP0 && cop0_rs == rs && cop0_function == function,
+            InstructionFormat::FR {
+                fmt,
+                function: fr_function,
+            } => op_code == COP1 && fmt == rs && fr_function == function,
+            InstructionFormat::FI { fmt, ft } => op_code == COP1 && fmt == rs && ft == rt & 1,
+            InstructionFormat::Special3 {
+                function: special3_function,
+                shamt: special3_shamt,
+            } => {
+                op_code == SPECIAL3
+                    && special3_function == function
+                    && (function != BSHFL || special3_shamt == shamt)
+            }
+            InstructionFormat::Rotate {
+                function: rotate_function,
+            } => {
+                let selector = if function == 2 { rs } else { shamt };
+                op_code == 0 && rotate_function == function && selector == 1
+            }
+        }
+    })
+}
+
+/// Turns an encoded word back into assembly, using the
+/// same instruction table as the assembler. Branch offsets
+/// and jump targets are written as the numbers encoded
+pub fn disassemble(word: u32) -> Option<String> {
+    let instruction = find_instruction(word)?;
+    let name = instruction.name;
+
+    let register = |shift: u32| format!("${}", Register::get_name((word >> shift & 0x1f) as u8));
+    let (rs, rt, rd) = (register(21), register(16), register(11));
+    let shamt = word >> 6 & 0x1f;
+    let immediate = match instruction.zero_extends_immediate() {
+        true => word as u16 as i32,
+        false => word as u16 as i16 as i32,
+    };
+
+    let text = match instruction.format {
+        InstructionFormat::R { .. } => match name {
+            "nop" => name.to_string(),
+            "syscall" | "break" => match word >> 6 & 0xfffff {
+                0 => name.to_string(),
+                code => format!("{} {}", name, code),
+            },
+            "mult" | "multu" | "div" | "divu" | "madd" | "maddu" | "msub" | "msubu" | "tge"
+            | "tgeu" | "tlt" | "tltu" | "teq" | "tne" => format!("{} {}, {}", name, rs, rt),
+            "clz" | "clo" => format!("{} {}, {}", name, rd, rs),
+            "jr" => format!("{} {}", name, rs),
+            "mfhi" | "mflo" => format!("{} {}", name, rd),
+            "sll" | "srl" => format!("{} {}, {}, {}", name, rd, rt, shamt),
+            _ => format!("{} {}, {}, {}", name, rd, rs, rt),
+        },
+        InstructionFormat::I { .. } => match name {
+            "lw" | "sw" => format!("{} {}, {}({})", name, rt, immediate, rs),
+            "lwc1" | "swc1" | "ldc1" | "sdc1" => {
+                format!("{} $f{}, {}({})", name, word >> 16 & 0x1f, immediate, rs)
+            }
+            "lui" => format!("{} {}, {}", name, rt, immediate),
+            "beq" | "bne" => format!("{} {}, {}, {}", name, rs, rt, immediate),
+            _ => format!("{} {}, {}, {}", name, rt, rs, immediate),
+        },
+        InstructionFormat::J { .. } => format!("{} {}", name, word & 0x03ffffff),
+        InstructionFormat::RegImm { .. } => format!("{} {}, {}", name, rs, immediate),
+        // Coprocessor registers are only known by number
+        InstructionFormat::Cop0 { .. } => match name {
+            "eret" => name.to_string(),
+            _ => format!("{} {}, ${}", name, rt, word >> 11 & 0x1f),
+        },
+        InstructionFormat::FR { function, .. } => {
+            let float = |shift: u32| format!("$f{}", word >> shift & 0x1f);
+            let (ft, fs, fd) = (float(16), float(11), float(6));
+            match name {
+                "mfc1" | "mtc1" => format!("{} {}, {}", name, rt, fs),
+                _ if name.starts_with("c.") => format!("{} {}, {}", name, fs, ft),
+                // add, sub, mul and div take two sources
+                _ if function < 4 => format!("{} {}, {}, {}", name, fd, fs, ft),
+                _ => format!("{} {}, {}", name, fd, fs),
+            }
+        }
+        InstructionFormat::FI { .. } => format!("{} {}", name, immediate),
+        InstructionFormat::Special3 { .. } => {
+            let last = word >> 11 & 0x1f;
+            match name {
+                "ext" => format!("{} {}, {}, {}, {}", name, rt, rs, shamt, last + 1),
+                "ins" => format!("{} {}, {}, {}, {}", name, rt, rs, shamt, last + 1 - shamt),
+                _ => format!("{} {}, {}", name, rd, rt),
+            }
+        }
+        InstructionFormat::Rotate { .. } => match name {
+            "rotr" => format!("{} {}, {}, {}", name, rd, rt, shamt),
+            _ => format!("{} {}, {}, {}", name, rd, rt, rs),
+        },
+    };
+
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extends_immediates_as_the_instruction_does() {
+        assert_eq!(disassemble(0x3408ffff).unwrap(), "ori $t0, $zero, 65535");
+        assert_eq!(disassemble(0x31088000).unwrap(), "andi $t0, $t0, 32768");
+        assert_eq!(disassemble(0x3c08ffff).unwrap(), "lui $t0, 65535");
+        assert_eq!(disassemble(0x2008ffff).unwrap(), "addi $t0, $zero, -1");
+        assert_eq!(disassemble(0x8fa8fffc).unwrap(), "lw $t0, -4($sp)");
+        assert_eq!(disassemble(0x0508ffff).unwrap(), "tgei $t0, -1");
+
+        // The text assembles back to the same word
+        for word in [
+            0x3408ffff, 0x31088000, 0x3c08ffff, 0x2008ffff, 0x8fa8fffc, 0x0000028c,
+        ] {
+            let source = disassemble(word).unwrap();
+            let program = crate::assemble_str(&source, &Default::default()).unwrap();
+            assert_eq!(program.section(".text").unwrap().words, [word]);
+        }
+    }
+
+    #[test]
+    fn writes_the_code_of_syscall_and_break() {
+        assert_eq!(disassemble(0x0000000c).unwrap(), "syscall");
+        assert_eq!(disassemble(0x0000028c).unwrap(), "syscall 10");
+        assert_eq!(disassemble(0x03ffffcd).unwrap(), "break 1048575");
+    }
+}
@@ -0,0 +1,320 @@
+//! Output formats for assembled programs.
+//!
+//! An [`Emitter`] writes a [`Program`] in one format. Several emitters
+//! can be given to an [`Assembler`](crate::Assembler), which hands each
+//! of them the same program. Other crates can add formats by implementing
+//! the trait:
+//!
+//! ```
+//! use std::io::{self, Write};
+//! use assembler::{emitter::Emitter, Assembler, Program};
+//!
+//! struct WordCount;
+//!
+//! impl Emitter for WordCount {
+//!     fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()> {
+//!         let words: usize = program.sections.iter().map(|section| section.words.len()).sum();
+//!         writeln!(out, "{}", words)
+//!     }
+//! }
+//!
+//! let mut assembler = Assembler::new("add $t0, $t1, $t2", &Default::default()).unwrap();
+//! let program = assembler.assemble().unwrap();
+//! assembler.add_emitter(Box::new(WordCount), Box::new(io::sink()));
+//! assembler.emit(&program).unwrap();
+//! ```
+
+use std::io::{self, Write};
+
+use crate::{disassembler, program::Program};
+
+/// Writes a program in some output format
+pub trait Emitter {
+    fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Names accepted by [`by_name`]
+pub const NAMES: [&str; 6] = ["logisim", "hex", "bin", "mif", "listing", "symbols"];
+
+/// Built-in emitter for a format name
+pub fn by_name(name: &str) -> Option<Box<dyn Emitter>> {
+    let emitter: Box<dyn Emitter> = match name {
+        "logisim" => Box::new(Logisim),
+        "hex" => Box::new(Hex),
+        "bin" => Box::new(Binary),
+        "mif" => Box::new(Mif),
+        "listing" => Box::new(Listing),
+        "symbols" => Box::new(SymbolMap),
+        _ => return None,
+    };
+    Some(emitter)
+}
+
+/// Longest gap, in words, written into a memory image. A longer hex
+/// gap is skipped with an address record, while the other images have
+/// no way to skip it, and would be too large to load, so they are not
+/// written
+pub const MAX_FILL: usize = 1 << 16;
+
+/// Piece of a memory image
+enum Run<'a> {
+    Words(&'a [u32]),
+    /// Number of words with no instruction, written as `Program::fill`
+    Gap(usize),
+}
+
+/// Memory image with the instructions of every code section, starting
+/// at the lowest address, which is the text segment unless code is
+/// placed before it
+fn image(program: &Program) -> Vec<Run<'_>> {
+    let code = program.code();
+    let mut address = code.first().map_or(0, |section| section.address);
+    let mut runs = Vec::new();
+    for section in code.into_iter().filter(|section| !section.words.is_empty()) {
+        let gap = section.address.wrapping_sub(address) / 4;
+        if gap > 0 {
+            runs.push(Run::Gap(gap as usize));
+        }
+        runs.push(Run::Words(&section.words));
+        address = section.address.wrapping_add(section.words.len() as u32 * 4);
+    }
+    runs
+}
+
+/// Fails for an image with a gap longer than [`MAX_FILL`],
+/// which a format that can not skip it would have to fill
+fn check_gaps(runs: &[Run], format: &str) -> io::Result<()> {
+    for run in runs {
+        if let Run::Gap(length) = run {
+            if *length > MAX_FILL {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "lacuna de {} palavras entre secoes, grande demais para o formato {}",
+                        length, format
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every word of the image, with gaps filled
+fn dense_image(program: &Program) -> impl Iterator<Item = u32> + '_ {
+    image(program).into_iter().flat_map(|run| match run {
+        Run::Words(words) => words.to_vec(),
+        Run::Gap(length) => vec![program.fill; length],
+    })
+}
+
+/// Code sections as a Logisim "v2.0 raw" memory image, with four
+/// entries per line. Gaps are written as a single "count*word" entry,
+/// and gaps longer than [`MAX_FILL`] are an error
+pub struct Logisim;
+
+impl Emitter for Logisim {
+    fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()> {
+        let runs = image(program);
+        check_gaps(&runs, "logisim")?;
+        out.write_all(b"v2.0 raw\n")?;
+        let mut index = 0;
+        let mut entry = |out: &mut dyn Write, text: String| {
+            index += 1;
+            let separator = if index % 4 == 0 { '\n' } else { ' ' };
+            write!(out, "{}{}", text, separator)
+        };
+        for run in runs {
+            match run {
+                Run::Words(words) => {
+                    for word in words {
+                        entry(out, format!("0x{:08x}", word))?;
+                    }
+                }
+                Run::Gap(length) => entry(out, format!("{}*0x{:08x}", length, program.fill))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Code sections as one hexadecimal word per line. Gaps longer than
+/// [`MAX_FILL`] are skipped with an "@index" line, the word index
+/// from the start of the image, as read by Verilog's `$readmemh`
+pub struct Hex;
+
+impl Emitter for Hex {
+    fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()> {
+        let mut index = 0;
+        for run in image(program) {
+            match run {
+                Run::Words(words) => {
+                    for word in words {
+                        writeln!(out, "{:08x}", word)?;
+                    }
+                    index += words.len();
+                }
+                Run::Gap(length) if length > MAX_FILL => {
+                    index += length;
+                    writeln!(out, "@{:x}", index)?;
+                }
+                Run::Gap(length) => {
+                    for _ in 0..length {
+                        writeln!(out, "{:08x}", program.fill)?;
+                    }
+                    index += length;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Code sections as big endian bytes, gaps included. Gaps longer
+/// than [`MAX_FILL`] are an error, checked before anything is written
+pub struct Binary;
+
+impl Emitter for Binary {
+    fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()> {
+        check_gaps(&image(program), "bin")?;
+        for word in dense_image(program) {
+            out.write_all(&word.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Code sections as a Quartus memory initialization file, addressed
+/// by word from 0. Gaps are written as a range of addresses, and gaps
+/// longer than [`MAX_FILL`] are an error
+pub struct Mif;
+
+impl Emitter for Mif {
+    fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()> {
+        let runs = image(program);
+        check_gaps(&runs, "mif")?;
+        let depth: usize = runs
+            .iter()
+            .map(|run| match run {
+                Run::Words(words) => words.len(),
+                Run::Gap(length) => *length,
+            })
+            .sum();
+        writeln!(out, "WIDTH=32;")?;
+        writeln!(out, "DEPTH={};", depth)?;
+        writeln!(out, "ADDRESS_RADIX=HEX;")?;
+        writeln!(out, "DATA_RADIX=HEX;")?;
+        writeln!(out, "CONTENT BEGIN")?;
+        let mut index = 0;
+        for run in runs {
+            match run {
+                Run::Words(words) => {
+                    for word in words {
+                        writeln!(out, "    {:x} : {:08x};", index, word)?;
+                        index += 1;
+                    }
+                }
+                Run::Gap(length) => {
+                    let last = index + length - 1;
+                    writeln!(out, "    [{:x}..{:x}] : {:08x};", index, last, program.fill)?;
+                    index += length;
+                }
+            }
+        }
+        writeln!(out, "END;")
+    }
+}
+
+/// Address, word and assembly of every word in every section
+pub struct Listing;
+
+impl Emitter for Listing {
+    fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()> {
+        for section in &program.sections {
+            if section.words.is_empty() {
+                continue;
+            }
+            writeln!(out, "{}", section.name)?;
+            for (index, word) in section.words.iter().enumerate() {
+                let address = section.address.wrapping_add(index as u32 * 4);
+                // Data is not decoded, as it rarely holds instructions
+                let assembly = match section.name.as_str() {
+                    ".text" | ".ktext" => disassembler::disassemble(*word),
+                    _ => None,
+                }
+                .unwrap_or_else(|| format!(".word 0x{:08x}", word));
+                writeln!(out, "0x{:08x}  0x{:08x}  {}", address, word, assembly)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Address and name of each symbol, ordered by address
+pub struct SymbolMap;
+
+impl Emitter for SymbolMap {
+    fn emit(&self, program: &Program, out: &mut dyn Write) -> io::Result<()> {
+        let mut symbols: Vec<_> = program.symbols.iter().collect();
+        symbols.sort_by_key(|symbol| symbol.address);
+        for symbol in symbols {
+            writeln!(out, "0x{:08x} {}", symbol.address, symbol.name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_str;
+
+    fn emit(emitter: &dyn Emitter, source: &str) -> io::Result<Vec<u8>> {
+        let program = assemble_str(source, &Default::default()).unwrap();
+        let mut out = Vec::new();
+        emitter.emit(&program, &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn fills_short_gaps() {
+        let hex = emit(&Hex, "nop\n.org 0x0040000c\neret").unwrap();
+        assert_eq!(
+            String::from_utf8(hex).unwrap(),
+            "00000000\n00000000\n00000000\n42000018\n"
+        );
+    }
+
+    #[test]
+    fn skips_long_gaps_only_in_hex() {
+        let source = "nop\n.ktext\neret";
+        let hex = emit(&Hex, source).unwrap();
+        assert_eq!(
+            String::from_utf8(hex).unwrap(),
+            "00000000\n@1ff00000\n42000018\n"
+        );
+        assert!(emit(&Binary, source).is_err());
+
+        assert!(emit(&Logisim, source).is_err());
+        assert!(emit(&Mif, source).is_err());
+
+        // Gaps up to MAX_FILL words are written
+        let source = "nop\n.org 0x00440000\neret";
+        let logisim = emit(&Logisim, source).unwrap();
+        assert_eq!(
+            String::from_utf8(logisim).unwrap(),
+            "v2.0 raw\n0x00000000 65535*0x00000000 0x42000018 "
+        );
+        let mif = String::from_utf8(emit(&Mif, source).unwrap()).unwrap();
+        assert!(mif.contains("DEPTH=65537;"));
+    }
+
+    #[test]
+    fn wraps_at_the_end_of_memory() {
+        let listing = emit(&Listing, ".org 0xfffffffc\nnop").unwrap();
+        assert!(String::from_utf8(listing)
+            .unwrap()
+            .contains("0xfffffffc  0x00000000  nop"));
+        assert!(emit(&Hex, ".org 0xfffffffc\nnop").is_ok());
+    }
+}
@@ -0,0 +1,565 @@
+use crate::{
+    label::{Label, Segment},
+    layout::Layout,
+};
+
+/// Integer expression in an immediate or data field, such as
+/// "end - start", "%hi(table) + 1" or "(SIZE << 2) | 1"
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(i64),
+    /// Label or constant
+    Symbol(String),
+    /// "." for the address being assembled
+    Location,
+    /// "1b" or "1f", the nearest numeric label before or after
+    LocalLabel {
+        number: String,
+        forward: bool,
+    },
+    Unary(char, Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+    /// "%hi(x)", the upper half to load with lui, corrected
+    /// for the sign extension of the lower half
+    Hi(Box<Expression>),
+    /// "%lo(x)", the lower half as a signed 16 bit value
+    Lo(Box<Expression>),
+    /// "%gp_rel(x)", the offset of x from $gp
+    GpRel(Box<Expression>),
+}
+
+/// A ".eqv", ".equ" or ".set" definition
+#[derive(Debug)]
+pub struct Constant {
+    pub name: String,
+    pub expression: Expression,
+}
+
+/// Result of evaluating an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Value {
+    pub value: i64,
+    /// Whether the value is an address, which is the case when it is a
+    /// label or "." plus or minus a number. The difference between two
+    /// addresses is a plain number
+    pub address: bool,
+}
+
+/// Symbols an expression is evaluated with
+pub struct Context<'a> {
+    pub labels: &'a [Label],
+    pub constants: &'a [Constant],
+    pub layout: &'a Layout,
+    /// Address of the instruction or data word with the expression
+    pub location: u32,
+    pub segment: Segment,
+    /// File with the expression, where its local labels are
+    pub file: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Location,
+    LocalLabel(String, bool),
+    /// "%hi", "%lo" or "%gp_rel"
+    Function(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+/// Operators by precedence, from the loosest binding, as in C.
+/// Comparisons and logical operators give 1 or 0
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        let start = index;
+        index += 1;
+
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '0'..='9' => {
+                while index < chars.len()
+                    && (chars[index].is_ascii_alphanumeric() || chars[index] == '_')
+                {
+                    index += 1;
+                }
+                let number: String = chars[start..index].iter().collect();
+                match local_label(&number) {
+                    Some((label, forward)) => Token::LocalLabel(label.to_string(), forward),
+                    None => Token::Number(parse_number(&number)?),
+                }
+            }
+            '\'' => {
+                let (value, length) = parse_character(&chars[index..])?;
+                index += length;
+                Token::Number(value)
+            }
+            _ if is_identifier_start(c) => {
+                while index < chars.len() && is_identifier_char(chars[index]) {
+                    index += 1;
+                }
+                match chars[start..index].iter().collect::<String>() {
+                    identifier if identifier == "." => Token::Location,
+                    identifier => Token::Identifier(identifier),
+                }
+            }
+            '%' if index < chars.len() && chars[index].is_ascii_alphabetic() => {
+                while index < chars.len()
+                    && (chars[index].is_ascii_alphanumeric() || chars[index] == '_')
+                {
+                    index += 1;
+                }
+                let name: String = chars[start..index].iter().collect();
+                if !["%hi", "%lo", "%gp_rel"].contains(&name.as_str()) {
+                    return Err(format!("Operador desconhecido: {}", name));
+                }
+                Token::Function(name)
+            }
+            '<' | '>' | '=' | '!' | '&' | '|' => {
+                let operator = match (c, chars.get(index)) {
+                    ('<', Some('<')) => "<<",
+                    ('>', Some('>')) => ">>",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('&', Some('&')) => "&&",
+                    ('|', Some('|')) => "||",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('!', _) => "!",
+                    ('&', _) => "&",
+                    ('|', _) => "|",
+                    _ => return Err(format!("Caractere invalido na expressao: {}", c)),
+                };
+                index += operator.len() - 1;
+                Token::Operator(operator)
+            }
+            '+' => Token::Operator("+"),
+            '-' => Token::Operator("-"),
+            '*' => Token::Operator("*"),
+            '/' => Token::Operator("/"),
+            '%' => Token::Operator("%"),
+            '^' => Token::Operator("^"),
+            '~' => Token::Operator("~"),
+            _ => return Err(format!("Caractere invalido na expressao: {}", c)),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Number and direction of a numeric label reference, such as "1f".
+/// "0b" followed by digits is a binary number instead
+fn local_label(word: &str) -> Option<(&str, bool)> {
+    let (number, forward) = match word.as_bytes().last()? {
+        b'f' => (&word[..word.len() - 1], true),
+        b'b' => (&word[..word.len() - 1], false),
+        _ => return None,
+    };
+    Label::is_numeric(number).then_some((number, forward))
+}
+
+/// Decimal, "0x" hexadecimal, "0b" binary or "0o" octal
+/// number, whose digits may be grouped with "_"
+fn parse_number(number: &str) -> Result<i64, String> {
+    let digits = number.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        Some("0o" | "0O") => (8, &digits[2..]),
+        _ => (10, &digits[..]),
+    };
+
+    // from_str_radix would also take a sign, which is an operator here
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(format!("Numero invalido: {}", number));
+    }
+    i64::from_str_radix(digits, radix).map_err(|_| format!("Numero fora de alcance: {}", number))
+}
+
+/// Value of a number written by itself, possibly negative, in any
+/// form a source accepts, for numbers given outside of a source
+pub fn parse_literal(text: &str) -> Option<i64> {
+    Expression::parse(text).ok()?.literal()
+}
+
+/// Value of a character literal such as 'a' or '\n', given what
+/// follows its opening quote, and the number of chars it takes
+fn parse_character(chars: &[char]) -> Result<(i64, usize), String> {
+    let (c, length) = match chars {
+        ['\\', escaped, ..] => {
+            let c = match escaped {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                '\\' | '\'' | '"' => *escaped,
+                _ => return Err(format!("Escape invalido: \\{}", escaped)),
+            };
+            (c, 2)
+        }
+        [c, ..] if *c != '\'' => (*c, 1),
+        _ => return Err("Caractere vazio".to_string()),
+    };
+
+    if chars.get(length) != Some(&'\'') {
+        return Err("Caractere nao fechado".to_string());
+    }
+    Ok((c as i64, length + 1))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(Token::Operator(operator)) = self.peek() {
+            let operator = *operator;
+            if !BINARY_OPERATORS[level].contains(&operator) {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Operator(operator @ ("-" | "+" | "~" | "!"))) => {
+                let operand = self.unary()?;
+                Ok(Expression::Unary(
+                    operator.chars().next().unwrap(),
+                    Box::new(operand),
+                ))
+            }
+            Some(Token::Number(value)) => Ok(Expression::Number(value)),
+            Some(Token::Identifier(name)) => Ok(Expression::Symbol(name)),
+            Some(Token::Location) => Ok(Expression::Location),
+            Some(Token::LocalLabel(number, forward)) => {
+                Ok(Expression::LocalLabel { number, forward })
+            }
+            Some(Token::Function(name)) => {
+                if self.next() != Some(Token::Open) {
+                    return Err(format!("Parenteses faltando depois de {}", name));
+                }
+                let operand = Box::new(self.parenthesized()?);
+                Ok(match name.as_str() {
+                    "%hi" => Expression::Hi(operand),
+                    "%lo" => Expression::Lo(operand),
+                    _ => Expression::GpRel(operand),
+                })
+            }
+            Some(Token::Open) => self.parenthesized(),
+            _ => Err("Expressao incompleta".to_string()),
+        }
+    }
+
+    /// Rest of a parenthesized expression, after its "("
+    fn parenthesized(&mut self) -> Result<Expression, String> {
+        let expression = self.binary(0)?;
+        if self.next() != Some(Token::Close) {
+            return Err("Parenteses nao fechados".to_string());
+        }
+        Ok(expression)
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let expression = parser.binary(0)?;
+        if parser.position < parser.tokens.len() {
+            return Err(format!("Expressao invalida: {}", text.trim()));
+        }
+        Ok(expression)
+    }
+
+    pub fn evaluate(&self, context: &Context) -> Result<Value, String> {
+        self.evaluate_with(context, &mut Vec::new())
+    }
+
+    /// `resolving` holds the constants being evaluated, to catch
+    /// definitions that depend on themselves
+    fn evaluate_with<'a>(
+        &'a self,
+        context: &'a Context,
+        resolving: &mut Vec<&'a str>,
+    ) -> Result<Value, String> {
+        let absolute = |value| Value {
+            value,
+            address: false,
+        };
+
+        match self {
+            Expression::Number(value) => Ok(absolute(*value)),
+            Expression::Location => Ok(Value {
+                value: context.location as i64,
+                address: true,
+            }),
+            Expression::LocalLabel { number, forward } => {
+                let location = context.location as i64;
+                let addresses = context
+                    .labels
+                    .iter()
+                    .filter(|label| {
+                        label.name == *number
+                            && label.segment == context.segment
+                            && label.file.as_deref() == context.file
+                    })
+                    .map(|label| label.address(context.layout) as i64);
+                let address = if *forward {
+                    addresses.filter(|address| *address > location).min()
+                } else {
+                    addresses.filter(|address| *address <= location).max()
+                };
+                let direction = if *forward { 'f' } else { 'b' };
+                address
+                    .map(|value| Value {
+                        value,
+                        address: true,
+                    })
+                    .ok_or_else(|| format!("Label local nao encontrado: {}{}", number, direction))
+            }
+            Expression::Symbol(name) => {
+                if let Some(label) = Label::find(context.labels, name, context.file) {
+                    return Ok(Value {
+                        value: label.address(context.layout) as i64,
+                        address: true,
+                    });
+                }
+                let constant = context
+                    .constants
+                    .iter()
+                    .find(|constant| constant.name == *name)
+                    .ok_or_else(|| format!("Simbolo nao encontrado: {}", name))?;
+                if resolving.contains(&name.as_str()) {
+                    return Err(format!("Definicao circular: {}", name));
+                }
+                resolving.push(name);
+                let value = constant.expression.evaluate_with(context, resolving);
+                resolving.pop();
+                value
+            }
+            Expression::Hi(operand) => {
+                let value = operand.evaluate_with(context, resolving)?.value;
+                Ok(absolute(((value + 0x8000) >> 16) & 0xFFFF))
+            }
+            Expression::Lo(operand) => {
+                let value = operand.evaluate_with(context, resolving)?.value;
+                Ok(absolute(((value & 0xFFFF) ^ 0x8000) - 0x8000))
+            }
+            Expression::GpRel(operand) => {
+                let value = operand.evaluate_with(context, resolving)?.value;
+                let offset = value - context.layout.gp() as i64;
+                if !(i16::MIN as i64..=i16::MAX as i64).contains(&offset) {
+                    return Err(format!("Endereco fora do alcance de $gp: 0x{:08x}", value));
+                }
+                Ok(absolute(offset))
+            }
+            Expression::Unary(operator, operand) => {
+                let value = operand.evaluate_with(context, resolving)?.value;
+                Ok(absolute(match operator {
+                    '-' => value.wrapping_neg(),
+                    '~' => !value,
+                    '!' => (value == 0) as i64,
+                    _ => value,
+                }))
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate_with(context, resolving)?;
+                let right = right.evaluate_with(context, resolving)?;
+                let (a, b) = (left.value, right.value);
+
+                let value = match *operator {
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    "*" => a.wrapping_mul(b),
+                    "/" | "%" if b == 0 => return Err("Divisao por zero".to_string()),
+                    "/" => a.wrapping_div(b),
+                    "%" => a.wrapping_rem(b),
+                    "<<" | ">>" if !(0..64).contains(&b) => {
+                        return Err(format!("Deslocamento invalido: {}", b))
+                    }
+                    "<<" => a << b,
+                    ">>" => a >> b,
+                    "&" => a & b,
+                    "|" => a | b,
+                    "^" => a ^ b,
+                    "==" => (a == b) as i64,
+                    "!=" => (a != b) as i64,
+                    "<" => (a < b) as i64,
+                    "<=" => (a <= b) as i64,
+                    ">" => (a > b) as i64,
+                    ">=" => (a >= b) as i64,
+                    "&&" => (a != 0 && b != 0) as i64,
+                    "||" => (a != 0 || b != 0) as i64,
+                    _ => unreachable!("Operador desconhecido: {}", operator),
+                };
+
+                // Moving an address keeps it one, and the distance
+                // between two addresses is a number
+                let address = match *operator {
+                    "+" => left.address != right.address,
+                    "-" => left.address && !right.address,
+                    _ => false,
+                };
+                Ok(Value { value, address })
+            }
+        }
+    }
+
+    /// Value of a number written by itself, possibly negative,
+    /// which does not depend on any symbol
+    pub fn literal(&self) -> Option<i64> {
+        match self {
+            Expression::Number(value) => Some(*value),
+            Expression::Unary('-', operand) => operand.literal().map(i64::wrapping_neg),
+            _ => None,
+        }
+    }
+
+    /// Whether the value may need 32 bits, as it uses a label, which
+    /// is any name that is not one of `constants`. Labels inside
+    /// %hi, %lo and %gp_rel only give 16 bits
+    pub fn uses_labels(&self, constants: &[Constant]) -> bool {
+        match self {
+            Expression::Number(_) | Expression::Location => false,
+            Expression::Hi(_) | Expression::Lo(_) | Expression::GpRel(_) => false,
+            Expression::Symbol(name) => !constants.iter().any(|constant| constant.name == *name),
+            Expression::LocalLabel { .. } => true,
+            Expression::Unary(_, operand) => operand.uses_labels(constants),
+            Expression::Binary(_, left, right) => {
+                left.uses_labels(constants) || right.uses_labels(constants)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assemble_str;
+
+    fn words(values: &str) -> Vec<u32> {
+        let source = format!(".data\n.word {}", values);
+        let program = assemble_str(&source, &Default::default()).unwrap();
+        program.section(".data").unwrap().words.clone()
+    }
+
+    #[test]
+    fn evaluates_with_c_precedence() {
+        assert_eq!(
+            words("1 + 2 * 3, (1 + 2) * 3, 1 << 2 | 1, -7 / 2, ~0"),
+            [7, 9, 5, -3i32 as u32, u32::MAX]
+        );
+    }
+
+    #[test]
+    fn hi_carries_the_sign_of_lo() {
+        assert_eq!(
+            words("%hi(305430528), %lo(305430528), %hi(305430527), %lo(305430527)"),
+            [0x1235, 0xffff_8000, 0x1234, 0x7fff]
+        );
+        for value in [0, 0x7fff, 0x8000, 0xffff, 0x1001_8004, 0xffff_ffffu32] {
+            let halves = words(&format!("%hi({0}), %lo({0})", value));
+            assert_eq!(
+                (halves[0] << 16).wrapping_add(halves[1]),
+                value,
+                "{:#x}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn reads_every_literal_form() {
+        assert_eq!(
+            words(r"0x1_0, 0b101, 0o17, 1_000, 'a', '\n', '\''"),
+            [16, 5, 15, 1000, 97, 10, 39]
+        );
+        for invalid in ["0b2", "0x", "''"] {
+            let source = format!(".data\n.word {}", invalid);
+            assert!(
+                assemble_str(&source, &Default::default()).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn parses_literals_for_the_command_line() {
+        assert_eq!(super::parse_literal("-0x10"), Some(-16));
+        assert_eq!(super::parse_literal("0b1_01"), Some(5));
+        assert_eq!(super::parse_literal("'a'"), Some(97));
+        assert_eq!(super::parse_literal("NAME"), None);
+        assert_eq!(super::parse_literal("1 + 1"), None);
+    }
+
+    #[test]
+    fn compares_to_one_or_zero() {
+        assert_eq!(
+            words("2 > 1, 2 < 1, 1 <= 1, 1 >= 2, 3 == 3, 3 != 3, !0, !5"),
+            [1, 0, 1, 0, 1, 0, 1, 0]
+        );
+        assert_eq!(
+            words("1 && 2, 1 && 0, 0 || 3, 0 || 0, 1 < 2 == 1, 1 | 2 && 0"),
+            [1, 0, 1, 0, 1, 0]
+        );
+        assert_eq!(words("1 << 2 < 5, 1 + 1 == 2, -1 < 0"), [1, 1, 1]);
+        assert!(assemble_str(".data\n.word 1 = 1", &Default::default()).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{label::Label, statement};
+
+/// Indentation of instructions and directives
+const INDENT: &str = "    ";
+
+/// Rewrites a source in a consistent layout: labels on their own line
+/// at the first column, instructions indented, operands separated by
+/// ", " and memory references written as "offset($base)". Directive
+/// arguments, such as the name and parameters of ".macro", are kept
+/// as written
+pub fn format_source(source: &str) -> String {
+    let mut formatted = String::new();
+
+    for line in source.lines() {
+        let mut line = line.trim();
+
+        let first = line.split_whitespace().next().unwrap_or_default();
+        if Label::is_label(first) {
+            formatted.push_str(first);
+            formatted.push('\n');
+            line = line[first.len()..].trim_start();
+            if line.is_empty() {
+                continue;
+            }
+        }
+
+        if line.is_empty() {
+            formatted.push('\n');
+            continue;
+        }
+
+        let name = line.split_whitespace().next().unwrap_or_default();
+        let arguments = line[name.len()..].trim();
+        formatted.push_str(INDENT);
+        formatted.push_str(name);
+
+        if name.starts_with('.') {
+            if !arguments.is_empty() {
+                formatted.push(' ');
+                formatted.push_str(arguments);
+            }
+            formatted.push('\n');
+            continue;
+        }
+
+        let operands: Vec<String> = statement::split_operands(arguments)
+            .iter()
+            .map(|operand| operand.replace(" (", "("))
+            .collect();
+        if !operands.is_empty() {
+            formatted.push(' ');
+            formatted.push_str(&operands.join(", "));
+        }
+        formatted.push('\n');
+    }
+
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_directive_arguments() {
+        let source = "\
+.macro inc (%r)
+loop:   addi %r,%r,1
+.end_macro
+main: .word  1,2
+lw $t0,4 ($sp)";
+        assert_eq!(
+            format_source(source),
+            "    .macro inc (%r)
+loop:
+    addi %r, %r, 1
+    .end_macro
+main:
+    .word 1,2
+    lw $t0, 4($sp)
+"
+        );
+    }
+}
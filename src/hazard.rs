@@ -0,0 +1,340 @@
+use std::fmt;
+
+use crate::{
+    expression::Expression,
+    label::{Label, Segment},
+    layout::Layout,
+    register::Register,
+    statement::{Operand, Statement},
+};
+
+/// Without forwarding a value is only available once the producer
+/// reaches WB, assuming the register file writes in the first half
+/// of the cycle and reads in the second one
+const NO_FORWARDING_DISTANCE: usize = 2;
+
+/// Pipeline parameters used to look for hazards
+/// in the instruction stream
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    /// Results are forwarded from EX/MEM and MEM/WB back to EX
+    pub forwarding: bool,
+    /// Instructions needed between a load and the first
+    /// instruction that uses the loaded register
+    pub load_use_distance: usize,
+    /// Instructions fetched after a branch or jump before it is
+    /// resolved, counting the delay slot. The pipeline does not
+    /// flush them, so the ones past the delay slot must be nops
+    pub branch_delay: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            forwarding: true,
+            load_use_distance: 1,
+            branch_delay: 0,
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Instructions needed between the producer and
+    /// an instruction that reads its result
+    fn required_distance(&self, producer: &Statement) -> usize {
+        match (self.forwarding, producer.is_load()) {
+            (true, true) => self.load_use_distance,
+            (true, false) => 0,
+            (false, true) => NO_FORWARDING_DISTANCE.max(self.load_use_distance),
+            (false, false) => NO_FORWARDING_DISTANCE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HazardKind {
+    /// Read after write on the given register
+    Data { register: u8 },
+    /// Read after write on the given coprocessor 1 register
+    FloatData { register: u8 },
+    /// Instruction fetched before the branch or jump is resolved
+    Control,
+}
+
+#[derive(Debug)]
+pub struct Hazard {
+    pub kind: HazardKind,
+    /// Source line of the instruction that causes the hazard
+    pub producer_line: usize,
+    /// Source line of the instruction affected by it
+    pub consumer_line: usize,
+    /// Nops that must be placed before the affected instruction
+    pub nops: usize,
+}
+
+impl fmt::Display for Hazard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            HazardKind::Data { register } => write!(
+                f,
+                "Hazard de dados: linha {} le ${} escrito na linha {}, {} nop(s) necessario(s)",
+                self.consumer_line,
+                Register::get_name(register),
+                self.producer_line,
+                self.nops
+            ),
+            HazardKind::FloatData { register } => write!(
+                f,
+                "Hazard de dados: linha {} le $f{} escrito na linha {}, {} nop(s) necessario(s)",
+                self.consumer_line, register, self.producer_line, self.nops
+            ),
+            HazardKind::Control => write!(
+                f,
+                "Hazard de controle: linha {} executa antes do desvio da linha {}, {} nop(s) necessario(s)",
+                self.consumer_line, self.producer_line, self.nops
+            ),
+        }
+    }
+}
+
+/// Finds the hazards that affect the instruction at `index`
+fn hazards_at(statements: &[Statement], index: usize, config: &PipelineConfig) -> Vec<Hazard> {
+    let consumer = &statements[index];
+    let mut hazards = Vec::new();
+
+    if consumer.is_nop() {
+        return hazards;
+    }
+
+    // General purpose registers, then coprocessor 1 ones
+    let reads = consumer
+        .read_registers()
+        .into_iter()
+        .filter(|&register| register != 0)
+        .map(|register| HazardKind::Data { register })
+        .chain(
+            consumer
+                .read_float_registers()
+                .into_iter()
+                .map(|register| HazardKind::FloatData { register }),
+        );
+    let mut producers = Vec::new();
+    for kind in reads {
+        // Only the closest write matters, older values are overwritten
+        let producer = (0..index).rev().find(|&i| match kind {
+            HazardKind::Data { register } => statements[i].written_register() == Some(register),
+            HazardKind::FloatData { register } => {
+                statements[i].written_float_registers().contains(&register)
+            }
+            HazardKind::Control => false,
+        });
+
+        // A register read twice, as in "add $t1, $t0, $t0", or both
+        // registers of a double are one hazard
+        let Some(producer) = producer.filter(|producer| !producers.contains(producer)) else {
+            continue;
+        };
+        producers.push(producer);
+
+        let distance = index - producer - 1;
+        let required = config.required_distance(&statements[producer]);
+        if distance < required {
+            hazards.push(Hazard {
+                kind,
+                producer_line: statements[producer].line,
+                consumer_line: consumer.line,
+                nops: required - distance,
+            });
+        }
+    }
+
+    // The delay slot always executes, only what comes after it is affected
+    let shadow_start = index.saturating_sub(config.branch_delay);
+    let branch =
+        (shadow_start..index.saturating_sub(1)).find(|&i| statements[i].is_branch_or_jump());
+    if let Some(branch) = branch {
+        let distance = index - branch - 1;
+        hazards.push(Hazard {
+            kind: HazardKind::Control,
+            producer_line: statements[branch].line,
+            consumer_line: consumer.line,
+            nops: config.branch_delay - distance,
+        });
+    }
+
+    hazards
+}
+
+/// Reports every hazard in the instruction stream
+pub fn find_hazards(statements: &[Statement], config: &PipelineConfig) -> Vec<Hazard> {
+    (0..statements.len())
+        .flat_map(|index| hazards_at(statements, index, config))
+        .collect()
+}
+
+/// Inserts the minimal amount of nops to remove every hazard, moving
+/// labels and literal branch offsets accordingly.
+/// Nops are placed right before the affected instruction and its
+/// label, so jumping to the label skips them. Instructions in a delay
+/// slot get their nops before the branch instead
+pub fn insert_nops(
+    statements: &mut Vec<Statement>,
+    labels: &mut [Label],
+    layout: &mut Layout,
+    config: &PipelineConfig,
+) -> Vec<Hazard> {
+    let mut found = Vec::new();
+    let mut index = 0;
+
+    while index < statements.len() {
+        let hazards = hazards_at(statements, index, config);
+        let nops = hazards.iter().map(|hazard| hazard.nops).max().unwrap_or(0);
+
+        if nops > 0 {
+            // An instruction in a delay slot must stay right after its
+            // branch, so the nops go before the branch instead
+            let in_delay_slot = index > 0 && statements[index - 1].is_branch_or_jump();
+            let position = if in_delay_slot { index - 1 } else { index };
+
+            shift_branch_offsets(statements, position, nops);
+            for label in labels.iter_mut() {
+                if label.segment == Segment::Text && label.line >= position {
+                    label.line += nops;
+                }
+            }
+            layout.shift(position, nops);
+
+            let line = statements[position].line;
+            for _ in 0..nops {
+                statements.insert(position, Statement::nop(line));
+            }
+            index += nops;
+        }
+
+        found.extend(hazards);
+        index += 1;
+    }
+
+    found
+}
+
+/// Fixes literal branch offsets that cross the position
+/// where `nops` instructions are about to be inserted
+fn shift_branch_offsets(statements: &mut [Statement], position: usize, nops: usize) {
+    for (index, statement) in statements.iter_mut().enumerate() {
+        if !statement.is_branch() {
+            continue;
+        }
+
+        // Offsets that depend on labels follow them, and the ones
+        // that do not parse are reported when encoding
+        let operand = statement.branch_offset_index();
+        let offset =
+            match Expression::parse(statement.constant(operand)).map(|offset| offset.literal()) {
+                Ok(Some(offset)) => offset as i32,
+                _ => continue,
+            };
+        let current = index as i32;
+        let target = current + 1 + offset;
+        let position = position as i32;
+
+        let new_offset = if offset >= 0 && current < position && position <= target {
+            offset + nops as i32
+        } else if offset < 0 && target < position && position <= current {
+            offset - nops as i32
+        } else {
+            continue;
+        };
+        statement.operands[operand] = Operand::Constant(new_offset.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble_str, Assembler, Options};
+
+    fn words_with_nops(source: &str) -> Vec<u32> {
+        let options = Options {
+            insert_nops: Some(PipelineConfig::default()),
+            ..Default::default()
+        };
+        let program = assemble_str(source, &options).unwrap();
+        program.section(".text").unwrap().words.clone()
+    }
+
+    #[test]
+    fn reports_a_register_read_twice_once() {
+        let mut assembler =
+            Assembler::new("lw $t0, 0($t1)\nadd $t1, $t0, $t0", &Default::default()).unwrap();
+        let hazards = assembler.check_hazards(&PipelineConfig::default(), false);
+        assert_eq!(hazards.len(), 1);
+    }
+
+    #[test]
+    fn inserts_nops_before_labels() {
+        let source = "\
+loop: lw $t0, 0($t1)
+use: add $t2, $t0, $t0
+beq $t2, $zero, use
+j loop";
+        assert_eq!(
+            words_with_nops(source),
+            [
+                0x8d280000, // lw $t0, 0($t1)
+                0x00000000, // nop inserted for the load
+                0x01085020, // use: add $t2, $t0, $t0
+                0x1140fffe, // beq $t2, $zero, use
+                0x00000000, // delay slot
+                0x08100000, // j loop
+                0x00000000, // delay slot
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_literal_branch_offsets() {
+        let source = "\
+.set noreorder
+beq $zero, $zero, 2
+nop
+lw $t0, 0($t1)
+add $t2, $t0, $t0
+beq $zero, $zero, -5
+nop";
+        assert_eq!(
+            words_with_nops(source),
+            [
+                0x10000003, // beq over the inserted nop
+                0x00000000, 0x8d280000, 0x00000000, // nop inserted for the load
+                0x01085020, 0x1000fffa, // beq back to the first beq
+                0x00000000,
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_float_registers() {
+        let hazards = |source: &str| -> Vec<String> {
+            let mut assembler = Assembler::new(source, &Default::default()).unwrap();
+            let hazards = assembler.check_hazards(&PipelineConfig::default(), false);
+            hazards.iter().map(|hazard| hazard.to_string()).collect()
+        };
+        assert_eq!(
+            hazards("lwc1 $f0, 0($t0)\nadd.s $f2, $f0, $f0"),
+            ["Hazard de dados: linha 2 le $f0 escrito na linha 1, 1 nop(s) necessario(s)"]
+        );
+        // Both halves of a double come from the same load
+        assert_eq!(hazards("ldc1 $f0, 0($t0)\nadd.d $f2, $f0, $f4").len(), 1);
+        assert_eq!(hazards("lwc1 $f1, 0($t0)\nmov.d $f2, $f0").len(), 1);
+        assert_eq!(hazards("lwc1 $f0, 0($t0)\nmfc1 $t1, $f0").len(), 1);
+        // $f0 is not $zero, and $t0 is not $f8
+        assert!(hazards("lw $t0, 0($t1)\nadd.s $f8, $f0, $f0").is_empty());
+        assert!(hazards("add.s $f0, $f1, $f2\nswc1 $f0, 0($t0)").is_empty());
+
+        assert_eq!(
+            words_with_nops("lwc1 $f0, 0($t0)\nc.lt.s $f0, $f2"),
+            [0xc5000000, 0x00000000, 0x4602003c]
+        );
+    }
+}
@@ -1,7 +1,14 @@
 use crate::instruction_format::InstructionFormat;
 
-pub const INSTRUCTIONS: [Instruction; 31] = [
+pub const INSTRUCTIONS: [Instruction; 98] = [
     // FORMAT R
+    Instruction {
+        name: "nop",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 0,
+        },
+    },
     Instruction {
         name: "sll",
         format: InstructionFormat::R {
@@ -121,6 +128,62 @@ pub const INSTRUCTIONS: [Instruction; 31] = [
             function: 43,
         },
     },
+    Instruction {
+        name: "syscall",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 12,
+        },
+    },
+    Instruction {
+        name: "break",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 13,
+        },
+    },
+    Instruction {
+        name: "tge",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 48,
+        },
+    },
+    Instruction {
+        name: "tgeu",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 49,
+        },
+    },
+    Instruction {
+        name: "tlt",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 50,
+        },
+    },
+    Instruction {
+        name: "tltu",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 51,
+        },
+    },
+    Instruction {
+        name: "teq",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 52,
+        },
+    },
+    Instruction {
+        name: "tne",
+        format: InstructionFormat::R {
+            op_code: 0,
+            function: 54,
+        },
+    },
     Instruction {
         name: "mul",
         format: InstructionFormat::R {
@@ -128,6 +191,93 @@ pub const INSTRUCTIONS: [Instruction; 31] = [
             function: 2,
         },
     },
+    Instruction {
+        name: "madd",
+        format: InstructionFormat::R {
+            op_code: 28,
+            function: 0,
+        },
+    },
+    Instruction {
+        name: "maddu",
+        format: InstructionFormat::R {
+            op_code: 28,
+            function: 1,
+        },
+    },
+    Instruction {
+        name: "msub",
+        format: InstructionFormat::R {
+            op_code: 28,
+            function: 4,
+        },
+    },
+    Instruction {
+        name: "msubu",
+        format: InstructionFormat::R {
+            op_code: 28,
+            function: 5,
+        },
+    },
+    Instruction {
+        name: "clz",
+        format: InstructionFormat::R {
+            op_code: 28,
+            function: 32,
+        },
+    },
+    Instruction {
+        name: "clo",
+        format: InstructionFormat::R {
+            op_code: 28,
+            function: 33,
+        },
+    },
+    // FORMAT SPECIAL3
+    Instruction {
+        name: "ext",
+        format: InstructionFormat::Special3 {
+            function: 0,
+            shamt: 0,
+        },
+    },
+    Instruction {
+        name: "ins",
+        format: InstructionFormat::Special3 {
+            function: 4,
+            shamt: 0,
+        },
+    },
+    Instruction {
+        name: "seb",
+        format: InstructionFormat::Special3 {
+            function: 32,
+            shamt: 16,
+        },
+    },
+    Instruction {
+        name: "seh",
+        format: InstructionFormat::Special3 {
+            function: 32,
+            shamt: 24,
+        },
+    },
+    Instruction {
+        name: "wsbh",
+        format: InstructionFormat::Special3 {
+            function: 32,
+            shamt: 2,
+        },
+    },
+    // FORMAT ROTATE
+    Instruction {
+        name: "rotr",
+        format: InstructionFormat::Rotate { function: 2 },
+    },
+    Instruction {
+        name: "rotrv",
+        format: InstructionFormat::Rotate { function: 6 },
+    },
     // FORMAT I
     Instruction {
         name: "beq",
@@ -173,6 +323,282 @@ pub const INSTRUCTIONS: [Instruction; 31] = [
         name: "sw",
         format: InstructionFormat::I { op_code: 43 },
     },
+    Instruction {
+        name: "lwc1",
+        format: InstructionFormat::I { op_code: 49 },
+    },
+    Instruction {
+        name: "ldc1",
+        format: InstructionFormat::I { op_code: 53 },
+    },
+    Instruction {
+        name: "swc1",
+        format: InstructionFormat::I { op_code: 57 },
+    },
+    Instruction {
+        name: "sdc1",
+        format: InstructionFormat::I { op_code: 61 },
+    },
+    // FORMAT REGIMM, with the operation in the rt field
+    Instruction {
+        name: "tgei",
+        format: InstructionFormat::RegImm { rt: 8 },
+    },
+    Instruction {
+        name: "tgeiu",
+        format: InstructionFormat::RegImm { rt: 9 },
+    },
+    Instruction {
+        name: "tlti",
+        format: InstructionFormat::RegImm { rt: 10 },
+    },
+    Instruction {
+        name: "tltiu",
+        format: InstructionFormat::RegImm { rt: 11 },
+    },
+    Instruction {
+        name: "teqi",
+        format: InstructionFormat::RegImm { rt: 12 },
+    },
+    Instruction {
+        name: "tnei",
+        format: InstructionFormat::RegImm { rt: 14 },
+    },
+    // COPROCESSOR 0, with the operation in the rs field
+    Instruction {
+        name: "mfc0",
+        format: InstructionFormat::Cop0 { rs: 0, function: 0 },
+    },
+    Instruction {
+        name: "mtc0",
+        format: InstructionFormat::Cop0 { rs: 4, function: 0 },
+    },
+    Instruction {
+        name: "eret",
+        format: InstructionFormat::Cop0 {
+            rs: 16,
+            function: 24,
+        },
+    },
+    // COPROCESSOR 1, with the operand format in the fmt field
+    Instruction {
+        name: "add.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 0,
+        },
+    },
+    Instruction {
+        name: "add.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 0,
+        },
+    },
+    Instruction {
+        name: "sub.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 1,
+        },
+    },
+    Instruction {
+        name: "sub.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 1,
+        },
+    },
+    Instruction {
+        name: "mul.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 2,
+        },
+    },
+    Instruction {
+        name: "mul.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 2,
+        },
+    },
+    Instruction {
+        name: "div.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 3,
+        },
+    },
+    Instruction {
+        name: "div.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 3,
+        },
+    },
+    Instruction {
+        name: "sqrt.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 4,
+        },
+    },
+    Instruction {
+        name: "sqrt.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 4,
+        },
+    },
+    Instruction {
+        name: "abs.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 5,
+        },
+    },
+    Instruction {
+        name: "abs.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 5,
+        },
+    },
+    Instruction {
+        name: "mov.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 6,
+        },
+    },
+    Instruction {
+        name: "mov.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 6,
+        },
+    },
+    Instruction {
+        name: "neg.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 7,
+        },
+    },
+    Instruction {
+        name: "neg.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 7,
+        },
+    },
+    Instruction {
+        name: "cvt.s.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 32,
+        },
+    },
+    Instruction {
+        name: "cvt.s.w",
+        format: InstructionFormat::FR {
+            fmt: 20,
+            function: 32,
+        },
+    },
+    Instruction {
+        name: "cvt.d.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 33,
+        },
+    },
+    Instruction {
+        name: "cvt.d.w",
+        format: InstructionFormat::FR {
+            fmt: 20,
+            function: 33,
+        },
+    },
+    Instruction {
+        name: "cvt.w.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 36,
+        },
+    },
+    Instruction {
+        name: "cvt.w.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 36,
+        },
+    },
+    Instruction {
+        name: "c.eq.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 50,
+        },
+    },
+    Instruction {
+        name: "c.eq.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 50,
+        },
+    },
+    Instruction {
+        name: "c.lt.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 60,
+        },
+    },
+    Instruction {
+        name: "c.lt.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 60,
+        },
+    },
+    Instruction {
+        name: "c.le.s",
+        format: InstructionFormat::FR {
+            fmt: 16,
+            function: 62,
+        },
+    },
+    Instruction {
+        name: "c.le.d",
+        format: InstructionFormat::FR {
+            fmt: 17,
+            function: 62,
+        },
+    },
+    Instruction {
+        name: "mfc1",
+        format: InstructionFormat::FR {
+            fmt: 0,
+            function: 0,
+        },
+    },
+    Instruction {
+        name: "mtc1",
+        format: InstructionFormat::FR {
+            fmt: 4,
+            function: 0,
+        },
+    },
+    Instruction {
+        name: "bc1f",
+        format: InstructionFormat::FI { fmt: 8, ft: 0 },
+    },
+    Instruction {
+        name: "bc1t",
+        format: InstructionFormat::FI { fmt: 8, ft: 1 },
+    },
     // FORMAT J
     Instruction {
         name: "j",
@@ -190,7 +616,13 @@ pub struct Instruction {
 }
 
 impl Instruction {
-    pub fn get_instruction(name: &str) -> Option<&Instruction> {
+    pub fn get_instruction(name: &str) -> Option<&'static Instruction> {
         INSTRUCTIONS.iter().find(|predicate| predicate.name == name)
     }
+
+    /// Logical immediates and lui take their 16 bits as they are,
+    /// while the other immediates are sign extended
+    pub fn zero_extends_immediate(&self) -> bool {
+        matches!(self.name, "andi" | "ori" | "lui")
+    }
 }
@@ -1,5 +1,92 @@
 pub enum InstructionFormat {
-    R { op_code: u8, function: u8 },
-    I { op_code: u8 },
-    J { op_code: u8 },
+    R {
+        op_code: u8,
+        function: u8,
+    },
+    I {
+        op_code: u8,
+    },
+    J {
+        op_code: u8,
+    },
+    /// I format with op_code 1, where rt selects the operation
+    RegImm {
+        rt: u8,
+    },
+    /// R format with op_code 16, where rs selects the operation
+    Cop0 {
+        rs: u8,
+        function: u8,
+    },
+    /// Coprocessor 1 register format, op_code(6) fmt(5) ft(5) fs(5)
+    /// fd(5) function(6). fmt is the precision, or the operation for
+    /// moves to and from general purpose registers
+    FR {
+        fmt: u8,
+        function: u8,
+    },
+    /// Coprocessor 1 immediate format, op_code(6) fmt(5) ft(5)
+    /// offset(16), used by the branches on the condition flag
+    FI {
+        fmt: u8,
+        ft: u8,
+    },
+    /// R format with op_code 31. ext and ins pack the bit field in rd
+    /// and shamt, while for function 32 shamt selects the operation
+    Special3 {
+        function: u8,
+        shamt: u8,
+    },
+    /// R format with op_code 0, as srl and srlv with a 1 in rs
+    /// or shamt that selects the rotate
+    Rotate {
+        function: u8,
+    },
+}
+
+/// Checks that a field fits in its width before it is packed
+fn field(value: u32, width: u32) -> u32 {
+    assert!(
+        value < 1 << width,
+        "Campo de {} bits invalido: {}",
+        width,
+        value
+    );
+    value
+}
+
+/// op_code of the REGIMM instructions
+pub const REGIMM: u8 = 1;
+/// op_code of the coprocessor 0 instructions
+pub const COP0: u8 = 16;
+/// op_code of the coprocessor 1 instructions
+pub const COP1: u8 = 17;
+/// op_code of the Release 2 instructions, such as ext and seb
+pub const SPECIAL3: u8 = 31;
+/// function of the SPECIAL3 instructions that shuffle bytes
+pub const BSHFL: u8 = 32;
+
+impl InstructionFormat {
+    /// op_code(6) rs(5) rt(5) rd(5) shamt(5) function(6)
+    pub fn encode_r(op_code: u8, rs: u8, rt: u8, rd: u8, shamt: u8, function: u8) -> u32 {
+        field(op_code as u32, 6) << 26
+            | field(rs as u32, 5) << 21
+            | field(rt as u32, 5) << 16
+            | field(rd as u32, 5) << 11
+            | field(shamt as u32, 5) << 6
+            | field(function as u32, 6)
+    }
+
+    /// op_code(6) rs(5) rt(5) immediate(16)
+    pub fn encode_i(op_code: u8, rs: u8, rt: u8, immediate: u16) -> u32 {
+        field(op_code as u32, 6) << 26
+            | field(rs as u32, 5) << 21
+            | field(rt as u32, 5) << 16
+            | immediate as u32
+    }
+
+    /// op_code(6) target(26)
+    pub fn encode_j(op_code: u8, target: u32) -> u32 {
+        field(op_code as u32, 6) << 26 | field(target, 26)
+    }
 }
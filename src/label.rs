@@ -1,81 +1,48 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-};
+use crate::layout::Layout;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    Text,
+    Data,
+}
 
 #[derive(Debug)]
 pub struct Label {
     pub name: String,
+    /// File defining the label, which is where a local label can be used
+    pub file: Option<String>,
+    pub segment: Segment,
+    /// Position of the labeled instruction or data word in its
+    /// segment, blank and label-only lines are not counted
     pub line: usize,
 }
 
 impl Label {
-    pub fn reference_to_relative_line(
-        labels: &Vec<Label>,
-        label_name: &str,
-        current_line_number: usize,
-    ) -> i32 {
-        let label_line = Label::find_label_line(labels, &label_name);
-        let current_line = current_line_number;
-        label_line as i32 - current_line as i32
-    }
-
     pub fn is_label(word: &str) -> bool {
-        word.chars().last() == Some(':')
+        word.ends_with(':')
     }
 
-    pub fn is_label_reference(word: &str) -> bool {
-        if let Ok(_) = word.parse::<i32>() {
-            return false;
-        }
-        true
+    /// Numeric labels, such as "1:", can be defined many times. They are
+    /// used as "1b" or "1f", for the nearest one before or after
+    pub fn is_numeric(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
     }
 
-    fn find_label_line(labels: &Vec<Label>, label_name: &str) -> usize {        
-        labels
-            .iter()
-            .find(|label| label.name == label_name)
-            .expect("Label nao encontrado")
-            .line
+    /// Numeric and ".L" labels can only be used in the file
+    /// defining them, and are left out of the symbol table
+    pub fn is_local(name: &str) -> bool {
+        name.starts_with(".L") || Label::is_numeric(name)
     }
 
-    pub fn find_label_line_address(labels: &Vec<Label>, label_name: &str, address: bool) -> usize {
-        let mul = if address{
-            4
-        } else {
-            1
-        };
-        (labels
-            .iter()
-            .find(|label| label.name == label_name)
-            .expect("Label nao encontrado")
-            .line * mul)
-            + 1048576
+    /// Label with the given name, as seen from `file`
+    pub fn find<'a>(labels: &'a [Label], name: &str, file: Option<&str>) -> Option<&'a Label> {
+        labels.iter().find(|label| {
+            label.name == name && (!Label::is_local(name) || label.file.as_deref() == file)
+        })
     }
 
-    pub fn find_labels(file_to_read: &str) -> Vec<Label> {
-        let file = File::open(file_to_read).expect("Erro ao abrir arquivo de entrada");
-        let reader = BufReader::new(file);
-        let mut labels: Vec<Label> = Vec::new();
-
-        for (index, line) in reader.lines().enumerate() {
-            let line = line.expect("Erro ao ler linha do arquivo de entrada");
-
-            let words: Vec<&str> = line.split_whitespace().collect();
-            if words.len() < 1 {
-                continue;
-            }
-
-            if Label::is_label(words[0]) {
-                let mut word = words[0].chars();
-                word.next_back();
-                labels.push(Label {
-                    name: word.as_str().to_string(),
-                    line: index,
-                })
-            }
-        }
-
-        labels
+    /// Address of the label in memory
+    pub fn address(&self, layout: &Layout) -> u32 {
+        layout.address(self.segment, self.line)
     }
 }
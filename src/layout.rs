@@ -0,0 +1,289 @@
+use crate::{
+    label::Segment,
+    program::{DATA_ADDRESS, KDATA_ADDRESS, KTEXT_ADDRESS, SDATA_ADDRESS},
+};
+
+/// Directives that switch sections
+pub const SECTIONS: [&str; 6] = [".text", ".data", ".ktext", ".kdata", ".sdata", ".sbss"];
+
+/// Sections reached from $gp with a 16 bit offset
+pub const SMALL_SECTIONS: [&str; 2] = [".sdata", ".sbss"];
+
+/// Where a chunk of a section starts in memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    /// Right after the previous chunk of the same section
+    Follow,
+    /// Fixed address, from ".org"
+    At(u32),
+    /// After the previous chunk of the same section, rounded up
+    /// to a multiple of the given number of bytes, from ".align"
+    Align(u32),
+}
+
+/// Run of instructions or data words placed one after the other,
+/// from `start` up to where the next chunk of the segment starts
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// Section directive, such as ".text" or ".kdata"
+    pub section: &'static str,
+    /// Index of the first instruction or data word in its segment
+    pub start: usize,
+    placement: Placement,
+    pub address: u32,
+}
+
+impl Chunk {
+    pub fn segment(&self) -> Segment {
+        segment(self.section)
+    }
+}
+
+/// Segment holding the instructions or data words of a section
+pub fn segment(section: &str) -> Segment {
+    match section {
+        ".text" | ".ktext" => Segment::Text,
+        _ => Segment::Data,
+    }
+}
+
+/// Addresses of the instructions and data words, which are kept in
+/// one list per segment, split in chunks by ".org", ".align" and
+/// by switching between user and kernel sections
+#[derive(Debug, Clone)]
+pub struct Layout {
+    text_address: u32,
+    gp: u32,
+    chunks: Vec<Chunk>,
+    /// Number of instructions and of data words
+    text_length: usize,
+    data_length: usize,
+}
+
+impl Layout {
+    pub fn new(text_address: u32, gp: u32) -> Layout {
+        let mut layout = Layout {
+            text_address,
+            gp,
+            chunks: Vec::new(),
+            text_length: 0,
+            data_length: 0,
+        };
+        layout.place(".text", 0, Placement::Follow);
+        layout.place(".data", 0, Placement::Follow);
+        layout
+    }
+
+    pub fn text_address(&self) -> u32 {
+        self.text_address
+    }
+
+    /// Value $gp is assumed to hold, for "%gp_rel"
+    pub fn gp(&self) -> u32 {
+        self.gp
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    fn base(&self, section: &str) -> u32 {
+        match section {
+            ".text" => self.text_address,
+            ".ktext" => KTEXT_ADDRESS,
+            ".kdata" => KDATA_ADDRESS,
+            ".sdata" | ".sbss" => SDATA_ADDRESS,
+            _ => DATA_ADDRESS,
+        }
+    }
+
+    /// Switches to `section`, where the next instruction or data word
+    /// is `start` in its segment. Returning to a section continues
+    /// after its last chunk
+    pub fn enter(&mut self, section: &'static str, start: usize) {
+        let current = self.current(segment(section));
+        if current.is_none_or(|chunk| chunk.section != section) {
+            self.place(section, start, Placement::Follow);
+        }
+    }
+
+    /// Starts a new chunk of `section` from `start`
+    pub fn place(&mut self, section: &'static str, start: usize, placement: Placement) {
+        self.chunks.push(Chunk {
+            section,
+            start,
+            placement,
+            address: 0,
+        });
+        self.resolve();
+    }
+
+    /// Number of instructions or data words in the segment
+    pub fn length(&self, segment: Segment) -> usize {
+        match segment {
+            Segment::Text => self.text_length,
+            Segment::Data => self.data_length,
+        }
+    }
+
+    /// Records that the segment grew or shrank to `length`, which moves
+    /// ".sbss" when the last chunk of ".sdata" gets longer
+    pub fn set_length(&mut self, segment: Segment, length: usize) {
+        match segment {
+            Segment::Text => self.text_length = length,
+            Segment::Data => self.data_length = length,
+        }
+        self.resolve();
+    }
+
+    /// Last chunk of the segment, which new instructions or data words go to
+    pub fn current(&self, segment: Segment) -> Option<&Chunk> {
+        self.chunks
+            .iter()
+            .rev()
+            .find(|chunk| chunk.segment() == segment)
+    }
+
+    /// Moves the chunks after `position` in the text segment, where
+    /// `count` instructions were inserted
+    pub fn shift(&mut self, position: usize, count: usize) {
+        for chunk in &mut self.chunks {
+            if chunk.segment() == Segment::Text && chunk.start > position {
+                chunk.start += count;
+            }
+        }
+        self.text_length += count;
+        self.resolve();
+    }
+
+    /// Index one past the last instruction or data word of the chunk at `index`
+    pub fn end(&self, index: usize) -> usize {
+        let chunk = &self.chunks[index];
+        self.chunks[index + 1..]
+            .iter()
+            .find(|next| next.segment() == chunk.segment())
+            .map_or(self.length(chunk.segment()), |next| next.start)
+    }
+
+    /// Whether a chunk starts at the instruction or data word at `index`
+    pub fn starts_at(&self, segment: Segment, index: usize) -> bool {
+        self.chunks
+            .iter()
+            .any(|chunk| chunk.segment() == segment && chunk.start == index)
+    }
+
+    /// Address of the instruction or data word at `index` in its
+    /// segment, which belongs to the last chunk starting before it
+    pub fn address(&self, segment: Segment, index: usize) -> u32 {
+        let chunk = self
+            .chunks
+            .iter()
+            .rev()
+            .find(|chunk| chunk.segment() == segment && chunk.start <= index)
+            .expect("Segmento sem chunk inicial");
+        chunk.address.wrapping_add((index - chunk.start) as u32 * 4)
+    }
+
+    /// Computes the address of every chunk. A chunk only depends on
+    /// the ones before it, and on its length for the ones after.
+    /// ".sbss" goes after all of ".sdata", so it is placed last
+    fn resolve(&mut self) {
+        // End of the last chunk of each section
+        let mut ends: Vec<(&'static str, u32)> = Vec::new();
+
+        let sbss = |chunk: &Chunk| chunk.section == ".sbss";
+        let order: Vec<usize> = (0..self.chunks.len())
+            .filter(|&index| !sbss(&self.chunks[index]))
+            .chain((0..self.chunks.len()).filter(|&index| sbss(&self.chunks[index])))
+            .collect();
+
+        for index in order {
+            let section = self.chunks[index].section;
+            let end_of = |name: &str| {
+                ends.iter()
+                    .find(|(section, _)| *section == name)
+                    .map(|(_, end)| *end)
+            };
+            let end = end_of(section)
+                .or_else(|| end_of(".sdata").filter(|_| section == ".sbss"))
+                .unwrap_or(self.base(section));
+
+            let address = match self.chunks[index].placement {
+                Placement::Follow => end,
+                Placement::At(address) => address,
+                Placement::Align(bytes) => end.wrapping_add(bytes - 1) & !(bytes - 1),
+            };
+            self.chunks[index].address = address;
+
+            let start = self.chunks[index].start;
+            let length = self.end(index) - start;
+            let end = address.wrapping_add(length as u32 * 4);
+            match ends.iter_mut().find(|(name, _)| *name == section) {
+                Some(entry) => entry.1 = end,
+                None => ends.push((section, end)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::{GP_ADDRESS, TEXT_ADDRESS};
+
+    fn addresses(layout: &Layout, section: &str) -> Vec<u32> {
+        layout
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.section == section)
+            .map(|chunk| chunk.address)
+            .collect()
+    }
+
+    #[test]
+    fn sbss_follows_sdata_defined_after_it() {
+        let mut layout = Layout::new(TEXT_ADDRESS, GP_ADDRESS);
+        layout.enter(".sbss", 0);
+        layout.set_length(Segment::Data, 1);
+        layout.enter(".sdata", 1);
+        layout.set_length(Segment::Data, 3);
+
+        assert_eq!(addresses(&layout, ".sdata"), [SDATA_ADDRESS]);
+        assert_eq!(addresses(&layout, ".sbss"), [SDATA_ADDRESS + 8]);
+    }
+
+    #[test]
+    fn sbss_follows_every_chunk_of_sdata() {
+        let mut layout = Layout::new(TEXT_ADDRESS, GP_ADDRESS);
+        layout.enter(".sdata", 0);
+        layout.set_length(Segment::Data, 1);
+        layout.enter(".sbss", 1);
+        layout.set_length(Segment::Data, 2);
+        layout.enter(".sdata", 2);
+        layout.set_length(Segment::Data, 4);
+
+        assert_eq!(
+            addresses(&layout, ".sdata"),
+            [SDATA_ADDRESS, SDATA_ADDRESS + 4]
+        );
+        assert_eq!(addresses(&layout, ".sbss"), [SDATA_ADDRESS + 12]);
+    }
+
+    #[test]
+    fn places_org_and_align_chunks() {
+        let mut layout = Layout::new(TEXT_ADDRESS, GP_ADDRESS);
+        layout.set_length(Segment::Text, 1);
+        layout.place(".text", 1, Placement::Align(16));
+        layout.set_length(Segment::Text, 2);
+        layout.place(".text", 2, Placement::At(0x0040_1000));
+        layout.set_length(Segment::Text, 3);
+
+        assert_eq!(layout.address(Segment::Text, 0), TEXT_ADDRESS);
+        assert_eq!(layout.address(Segment::Text, 1), TEXT_ADDRESS + 16);
+        assert_eq!(layout.address(Segment::Text, 2), 0x0040_1000);
+
+        // Instructions inserted before a chunk move it
+        layout.shift(0, 4);
+        assert_eq!(layout.address(Segment::Text, 5), TEXT_ADDRESS + 32);
+    }
+}
@@ -0,0 +1,42 @@
+//! MIPS assembler producing Logisim memory images.
+//!
+//! The whole pipeline runs in memory, so the assembler can be
+//! embedded in other tools without touching the filesystem:
+//!
+//! ```
+//! let program = assembler::assemble_str("add $t0, $t1, $t2", &Default::default()).unwrap();
+//! assert_eq!(program.section(".text").unwrap().words, vec![0x012a4020]);
+//! ```
+
+pub mod assembler;
+pub mod builder;
+mod delay_slot;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod emitter;
+mod expression;
+pub mod formatter;
+pub mod hazard;
+pub mod instruction;
+pub mod instruction_format;
+mod label;
+mod layout;
+mod preprocessor;
+pub mod program;
+mod pseudo;
+pub mod register;
+mod statement;
+
+pub use assembler::{Assembler, Options, SourceFile};
+pub use diagnostic::{Diagnostic, Expansion, Location};
+pub use expression::parse_literal;
+pub use program::{Program, Section, Symbol};
+
+/// Assembles a source held in memory
+pub fn assemble_str(source: &str, options: &Options) -> Result<Program, Vec<Diagnostic>> {
+    let mut assembler = Assembler::new(source, options)?;
+    if let Some(config) = &options.insert_nops {
+        assembler.check_hazards(config, true);
+    }
+    assembler.assemble()
+}
@@ -1,20 +1,204 @@
-mod assembler;
-mod instruction;
-mod instruction_format;
-mod label;
-mod register;
+mod cli;
 
-use assembler::Assembler;
-use text_io::read;
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    process,
+};
 
-// TODO: suporte a registradores com nome
-// ex: $t0, $s0, $sp
+use assembler::{disassembler, emitter, formatter, Assembler, Diagnostic, SourceFile};
+use cli::{Args, Command, Output};
 
-// TODO: exportar para arquivo binario e
-// hexadecimal
+const EXIT_DIAGNOSTICS: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
 
 fn main() {
-    let file_to_read: String = read!();
-    let assembler = Assembler::new(file_to_read.as_ref());
-    assembler.assemble();
+    let args = cli::parse(env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("{}\nUse --help para ver as opcoes", message);
+        process::exit(EXIT_USAGE);
+    });
+
+    if args.help {
+        print!("{}", cli::HELP);
+        return;
+    }
+
+    match args.command {
+        Command::Asm | Command::Check => assemble(&args),
+        Command::Disasm => disassemble(&args),
+        Command::Fmt => format(&args),
+    }
+}
+
+fn read_input(path: &str) -> String {
+    let result = if path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        fs::read_to_string(path)
+    };
+
+    result.unwrap_or_else(|error| {
+        eprintln!("Erro ao ler arquivo de entrada {}: {}", path, error);
+        process::exit(EXIT_IO);
+    })
+}
+
+fn write_output(path: &str, contents: &[u8]) {
+    let result = if path == "-" {
+        io::stdout().write_all(contents)
+    } else {
+        fs::write(path, contents)
+    };
+
+    if let Err(error) = result {
+        eprintln!("Erro ao escrever no arquivo {}: {}", path, error);
+        process::exit(EXIT_IO);
+    }
+}
+
+/// Assembles every input as a single source, one after the other
+fn assemble(args: &Args) {
+    let sources: Vec<SourceFile> = args
+        .inputs
+        .iter()
+        .map(|path| SourceFile {
+            path: Some(path.clone()),
+            text: read_input(path),
+        })
+        .collect();
+
+    let mut assembler = Assembler::from_sources(&sources, &args.options)
+        .unwrap_or_else(|diagnostics| fail(diagnostics));
+    if args.check_hazards {
+        for hazard in assembler.check_hazards(&args.pipeline, args.insert_nops) {
+            eprintln!("{}", hazard);
+        }
+    }
+    let program = assembler
+        .assemble()
+        .unwrap_or_else(|diagnostics| fail(diagnostics));
+
+    if args.command == Command::Check {
+        return;
+    }
+
+    let default_output = Output {
+        path: "out.bin".to_string(),
+        format: args.format.clone(),
+    };
+    let outputs = match args.outputs.as_slice() {
+        [] => std::slice::from_ref(&default_output),
+        outputs => outputs,
+    };
+    for output in outputs {
+        let emitter = emitter::by_name(&output.format).expect("Formato validado pelo cli");
+        assembler.add_emitter(emitter, Box::new(OutputFile::create(&output.path)));
+    }
+
+    if let Err(error) = assembler.emit(&program) {
+        eprintln!("Erro ao escrever a saida: {}", error);
+        process::exit(EXIT_IO);
+    }
+}
+
+/// Output of an emitter, naming its file in write errors
+struct OutputFile {
+    path: String,
+    writer: Box<dyn Write>,
+}
+
+impl OutputFile {
+    fn create(path: &str) -> OutputFile {
+        let writer: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            let file = fs::File::create(path).unwrap_or_else(|error| {
+                eprintln!("Erro ao escrever no arquivo {}: {}", path, error);
+                process::exit(EXIT_IO);
+            });
+            Box::new(io::BufWriter::new(file))
+        };
+        OutputFile {
+            path: path.to_string(),
+            writer,
+        }
+    }
+
+    fn with_path(&self, error: io::Error) -> io::Error {
+        io::Error::new(error.kind(), format!("{}: {}", self.path, error))
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer
+            .write(buf)
+            .map_err(|error| self.with_path(error))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush().map_err(|error| self.with_path(error))
+    }
+}
+
+fn fail(diagnostics: Vec<Diagnostic>) -> ! {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    process::exit(EXIT_DIAGNOSTICS);
+}
+
+/// Reads a Logisim "v2.0 raw" image, or plain hexadecimal
+/// words, and writes the assembly for each word. A "count*word"
+/// entry is that word repeated, written once as "count*assembly"
+/// when it is longer than the gaps the hex format fills, and
+/// "@index" address records from the hex format are skipped
+fn disassemble(args: &Args) {
+    let image = read_input(&args.inputs[0]);
+    let image = image.strip_prefix("v2.0 raw").unwrap_or(&image);
+
+    let mut assembly = String::new();
+    for entry in image.split_whitespace() {
+        if entry.starts_with('@') {
+            continue;
+        }
+        let invalid = || -> ! {
+            eprintln!("Palavra invalida: {}", entry);
+            process::exit(EXIT_DIAGNOSTICS);
+        };
+        let (count, word) = match entry.split_once('*') {
+            Some((count, word)) => (count.parse().unwrap_or_else(|_| invalid()), word),
+            None => (1, entry),
+        };
+        let hex = word.strip_prefix("0x").unwrap_or(word);
+        let instruction = u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(disassembler::disassemble)
+            .unwrap_or_else(|| invalid());
+
+        if count > emitter::MAX_FILL {
+            assembly.push_str(&format!("{}*{}\n", count, instruction));
+            continue;
+        }
+        for _ in 0..count {
+            assembly.push_str(&instruction);
+            assembly.push('\n');
+        }
+    }
+
+    let output = args
+        .outputs
+        .first()
+        .map_or("-", |output| output.path.as_str());
+    write_output(output, assembly.as_bytes());
+}
+
+/// Formats each input in place, or to stdout when reading from stdin
+fn format(args: &Args) {
+    for path in &args.inputs {
+        let formatted = formatter::format_source(&read_input(path));
+        write_output(path, formatted.as_bytes());
+    }
 }
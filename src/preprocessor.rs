@@ -0,0 +1,863 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    assembler::{Options, SourceFile},
+    diagnostic::{Diagnostic, Expansion, Location},
+    expression::{Constant, Context, Expression},
+    label::{Label, Segment},
+    layout::Layout,
+    statement,
+};
+
+/// Limit of nested macro calls, which stops recursive macros
+const MAX_DEPTH: usize = 64;
+/// Limit of ".rept" repetitions, far more than fits in a memory
+const MAX_REPEAT: i64 = 1 << 16;
+
+/// Where a line came from, to point diagnostics to it
+#[derive(Debug, Clone, Default)]
+pub struct Origin {
+    /// None for a source given in memory
+    pub file: Option<String>,
+    /// ".include" directives that led to the file, innermost first
+    pub includes: Vec<Location>,
+    /// Macro calls the line was expanded from, innermost first
+    pub expansions: Vec<Expansion>,
+}
+
+impl Origin {
+    pub fn diagnostic(&self, line: usize, message: String) -> Diagnostic {
+        Diagnostic {
+            file: self.file.clone(),
+            line,
+            message,
+            expansions: self.expansions.clone(),
+            includes: self.includes.clone(),
+        }
+    }
+
+    fn location(&self, line: usize) -> Location {
+        Location {
+            file: self.file.clone(),
+            line,
+        }
+    }
+}
+
+/// Source line after includes and macros are expanded
+#[derive(Clone)]
+pub struct SourceLine {
+    pub text: String,
+    /// Line where the text was written, starting at 1. Inside
+    /// a macro, this is the line in the macro definition
+    pub line: usize,
+    pub origin: Origin,
+}
+
+/// A ".macro name(%a, %b)" ... ".end_macro" definition
+#[derive(Clone)]
+struct Macro {
+    name: String,
+    /// Parameter names, with their "%"
+    parameters: Vec<String>,
+    /// Lines between the directives
+    body: Vec<SourceLine>,
+    definition: Location,
+}
+
+/// An ".if", ".ifdef" or ".ifndef" block being read
+struct Condition {
+    /// Whether the lines read now are assembled
+    active: bool,
+    /// Whether the enclosing block is active
+    parent_active: bool,
+    /// Whether the ".if" part or an ".elseif" part was taken
+    taken: bool,
+    in_else: bool,
+    /// Position of the directive in the lines being read
+    index: usize,
+}
+
+struct Preprocessor<'a> {
+    include_paths: &'a [PathBuf],
+    macros: Vec<Macro>,
+    constants: Vec<Constant>,
+    /// Number of expansions so far, which makes their labels unique
+    expansions: usize,
+    lines: Vec<SourceLine>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Expands the includes, macros and conditional blocks of the sources,
+/// which are read one after the other, and collects their constants.
+/// Macros must be defined before they are called, and a later definition
+/// with the same name and number of parameters replaces the earlier one
+pub fn expand(
+    sources: &[SourceFile],
+    options: &Options,
+) -> Result<(Vec<SourceLine>, Vec<Constant>), Vec<Diagnostic>> {
+    let mut preprocessor = Preprocessor {
+        include_paths: &options.include_paths,
+        macros: Vec::new(),
+        constants: options
+            .defines
+            .iter()
+            .map(|(name, value)| Constant {
+                name: name.clone(),
+                expression: Expression::Number(*value),
+            })
+            .collect(),
+        expansions: 0,
+        lines: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+
+    for source in sources {
+        let origin = Origin {
+            file: source.path.clone(),
+            ..Default::default()
+        };
+        preprocessor.process(&split_lines(&source.text, &origin));
+    }
+
+    if !preprocessor.diagnostics.is_empty() {
+        return Err(preprocessor.diagnostics);
+    }
+    Ok((preprocessor.lines, preprocessor.constants))
+}
+
+fn split_lines(text: &str, origin: &Origin) -> Vec<SourceLine> {
+    text.lines()
+        .enumerate()
+        .map(|(index, text)| SourceLine {
+            text: text.to_string(),
+            // Lines start at 1
+            line: index + 1,
+            origin: origin.clone(),
+        })
+        .collect()
+}
+
+impl Preprocessor<'_> {
+    fn process(&mut self, lines: &[SourceLine]) {
+        let mut conditions: Vec<Condition> = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            index += 1;
+
+            let mut words = line.text.split_whitespace();
+            let directive = words.next();
+            let active = conditions.last().is_none_or(|condition| condition.active);
+
+            let result = match directive {
+                Some(".if" | ".ifdef" | ".ifndef") => {
+                    // Conditions in skipped blocks are not evaluated
+                    let result = match active {
+                        true => self.condition(line, directive.unwrap()),
+                        false => Ok(false),
+                    };
+                    let taken = *result.as_ref().unwrap_or(&false);
+                    conditions.push(Condition {
+                        active: taken,
+                        parent_active: active,
+                        taken,
+                        in_else: false,
+                        index: index - 1,
+                    });
+                    result.map(|_| ())
+                }
+                Some(".elseif") => match conditions.last_mut() {
+                    Some(condition) if !condition.in_else => {
+                        // Only evaluated when no part before it was taken
+                        let result = match condition.parent_active && !condition.taken {
+                            true => self.condition(line, ".elseif"),
+                            false => Ok(false),
+                        };
+                        condition.active = *result.as_ref().unwrap_or(&false);
+                        condition.taken |= condition.active;
+                        result.map(|_| ())
+                    }
+                    Some(_) => Err(".elseif depois de .else".to_string()),
+                    None => Err(".elseif sem .if".to_string()),
+                },
+                Some(".else") => match conditions.last_mut() {
+                    Some(condition) if !condition.in_else => {
+                        condition.in_else = true;
+                        condition.active = condition.parent_active && !condition.taken;
+                        Ok(())
+                    }
+                    Some(_) => Err(".else repetido".to_string()),
+                    None => Err(".else sem .if".to_string()),
+                },
+                Some(".endif") => match conditions.pop() {
+                    Some(_) => Ok(()),
+                    None => Err(".endif sem .if".to_string()),
+                },
+                // Skipped lines do not define anything
+                _ if !active => Ok(()),
+                Some(".eqv" | ".equ") => self.define_constant(&line.text),
+                Some(".set") if !matches!(words.next(), Some("reorder" | "noreorder")) => {
+                    self.define_constant(&line.text)
+                }
+                Some(".macro") => match Preprocessor::find_end(&lines[index..]) {
+                    Ok(length) => {
+                        let body = lines[index..index + length].to_vec();
+                        index += length + 1;
+                        self.define(line, body)
+                    }
+                    Err(error) => {
+                        // Without its end, the rest would be read as the body
+                        index = lines.len();
+                        Err(error)
+                    }
+                },
+                Some(".end_macro") => Err(".end_macro sem .macro".to_string()),
+                Some(".include") => self.include(line),
+                Some(directive @ (".rept" | ".irp")) => {
+                    match Preprocessor::find_repeat_end(&lines[index..]) {
+                        Ok(length) => {
+                            let body = &lines[index..index + length];
+                            index += length + 1;
+                            self.repeat(line, directive, body)
+                        }
+                        Err(error) => {
+                            index = lines.len();
+                            Err(error)
+                        }
+                    }
+                }
+                Some(".endr") => Err(".endr sem .rept".to_string()),
+                _ => self.line(line),
+            };
+
+            if let Err(message) = result {
+                let diagnostic = line.origin.diagnostic(line.line, message);
+                self.diagnostics.push(diagnostic);
+            }
+        }
+
+        // Blocks end in the file or macro they start in
+        for condition in conditions {
+            let line = &lines[condition.index];
+            let message = ".if sem .endif".to_string();
+            self.diagnostics
+                .push(line.origin.diagnostic(line.line, message));
+        }
+    }
+
+    /// Whether the block of an ".if", ".elseif", ".ifdef" or ".ifndef"
+    /// is taken.
+    /// Only constants defined before the directive can be used
+    fn condition(&self, line: &SourceLine, directive: &str) -> Result<bool, String> {
+        let argument = line.text.trim_start()[directive.len()..].trim();
+        if argument.is_empty() {
+            return Err("Condicao faltando".to_string());
+        }
+
+        let defined = self
+            .constants
+            .iter()
+            .any(|constant| constant.name == argument);
+        match directive {
+            ".ifdef" => Ok(defined),
+            ".ifndef" => Ok(!defined),
+            _ => Ok(self.evaluate(argument)? != 0),
+        }
+    }
+
+    /// Value of an expression in a directive, which can only
+    /// use the constants defined before it
+    fn evaluate(&self, expression: &str) -> Result<i64, String> {
+        let context = Context {
+            labels: &[],
+            constants: &self.constants,
+            layout: &Layout::new(0, 0),
+            location: 0,
+            segment: Segment::Text,
+            file: None,
+        };
+        Ok(Expression::parse(expression)?.evaluate(&context)?.value)
+    }
+
+    /// Number of lines repeated by a ".rept" or ".irp", up to
+    /// its ".endr", which may hold other repetitions
+    fn find_repeat_end(lines: &[SourceLine]) -> Result<usize, String> {
+        let mut depth = 0;
+        for (index, line) in lines.iter().enumerate() {
+            match line.text.split_whitespace().next() {
+                Some(".rept" | ".irp") => depth += 1,
+                Some(".endr") if depth == 0 => return Ok(index),
+                Some(".endr") => depth -= 1,
+                _ => {}
+            }
+        }
+        Err(".rept sem .endr".to_string())
+    }
+
+    /// Reads the body of ".rept count" count times, or the body of
+    /// ".irp symbol, a, b" once for each value, with "\symbol" replaced
+    /// by the value. Each pass is read as if it were written out, so
+    /// labels and "." follow the position of the repeated lines
+    fn repeat(
+        &mut self,
+        line: &SourceLine,
+        directive: &str,
+        body: &[SourceLine],
+    ) -> Result<(), String> {
+        let arguments = line.text.trim_start()[directive.len()..].trim();
+
+        if directive == ".rept" {
+            if arguments.is_empty() {
+                return Err("Contagem faltando em .rept".to_string());
+            }
+            let count = self.evaluate(arguments)?;
+            if !(0..=MAX_REPEAT).contains(&count) {
+                return Err(format!("Contagem de .rept invalida: {}", count));
+            }
+            for _ in 0..count {
+                self.process(body);
+            }
+            return Ok(());
+        }
+
+        let mut values = statement::split_operands(arguments);
+        if values.is_empty() {
+            return Err("Simbolo faltando em .irp".to_string());
+        }
+        let symbol = values.remove(0);
+        let valid = symbol
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && symbol.chars().all(is_identifier_char);
+        if !valid {
+            return Err(format!("Simbolo invalido em .irp: {}", symbol));
+        }
+
+        for value in values {
+            let lines: Vec<SourceLine> = body
+                .iter()
+                .map(|line| SourceLine {
+                    text: replace_identifiers(&line.text, |prefix, identifier| {
+                        (prefix == Some('\\') && identifier == symbol).then(|| value.clone())
+                    }),
+                    ..line.clone()
+                })
+                .collect();
+            self.process(&lines);
+        }
+        Ok(())
+    }
+
+    /// Parses ".eqv NAME value", or its ".equ" and
+    /// ".set" forms, which may separate both with a comma
+    fn define_constant(&mut self, text: &str) -> Result<(), String> {
+        let text = text.trim_start();
+        let arguments = text[text.find(char::is_whitespace).unwrap_or(text.len())..].trim();
+        let end = arguments
+            .find(|c: char| c == ',' || c.is_whitespace())
+            .unwrap_or(arguments.len());
+        let name = &arguments[..end];
+        let rest = arguments[end..].trim_start();
+        let value = rest.strip_prefix(',').unwrap_or(rest).trim();
+
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("Nome de constante invalido: {}", name));
+        }
+        if value.is_empty() {
+            return Err(format!("Valor faltando para a constante {}", name));
+        }
+        if self.constants.iter().any(|constant| constant.name == name) {
+            return Err(format!("Constante redefinida: {}", name));
+        }
+
+        self.constants.push(Constant {
+            name: name.to_string(),
+            expression: Expression::parse(value)?,
+        });
+        Ok(())
+    }
+
+    /// Number of lines in a macro body, up to its ".end_macro"
+    fn find_end(lines: &[SourceLine]) -> Result<usize, String> {
+        for (index, line) in lines.iter().enumerate() {
+            match line.text.split_whitespace().next() {
+                Some(".end_macro") => return Ok(index),
+                Some(".macro") => {
+                    return Err(format!(
+                        "Macro definida dentro de outra na linha {}",
+                        line.line
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Err(".macro sem .end_macro".to_string())
+    }
+
+    /// Parses the ".macro" line of a definition
+    fn define(&mut self, header: &SourceLine, body: Vec<SourceLine>) -> Result<(), String> {
+        if !header.origin.expansions.is_empty() {
+            return Err("Macro definida dentro de outra".to_string());
+        }
+
+        let text = header.text.trim_start().trim_start_matches(".macro");
+        let (name, rest) = Preprocessor::split_name(text);
+        if name.is_empty() {
+            return Err("Nome de macro faltando".to_string());
+        }
+
+        let parameters = Preprocessor::split_arguments(rest);
+        for parameter in &parameters {
+            let valid = parameter.len() > 1
+                && parameter.starts_with('%')
+                && parameter[1..].chars().all(is_identifier_char);
+            if !valid {
+                return Err(format!("Parametro de macro invalido: {}", parameter));
+            }
+        }
+
+        self.macros.push(Macro {
+            name: name.to_string(),
+            parameters,
+            body,
+            definition: header.origin.location(header.line),
+        });
+        Ok(())
+    }
+
+    /// Reads the lines of an included file in place of the directive
+    fn include(&mut self, line: &SourceLine) -> Result<(), String> {
+        let argument = line.text.trim_start()[".include".len()..].trim();
+        let name = argument
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| "Diretiva .include invalida".to_string())?;
+
+        let path = self
+            .resolve(name, line.origin.file.as_deref())
+            .ok_or_else(|| format!("Arquivo incluido nao encontrado: {}", name))?;
+        let file = path.display().to_string();
+
+        // The including files are the ones in the chain of this line
+        let chain: Vec<&str> = line
+            .origin
+            .file
+            .iter()
+            .chain(
+                line.origin
+                    .includes
+                    .iter()
+                    .filter_map(|include| include.file.as_ref()),
+            )
+            .map(String::as_str)
+            .collect();
+        if chain.iter().any(|including| same_file(including, &path)) {
+            let mut cycle: Vec<&str> = chain.iter().rev().copied().collect();
+            cycle.push(&file);
+            return Err(format!("Include recursivo: {}", cycle.join(" -> ")));
+        }
+
+        let text = fs::read_to_string(&path)
+            .map_err(|error| format!("Erro ao ler arquivo incluido {}: {}", file, error))?;
+
+        let mut includes = vec![line.origin.location(line.line)];
+        includes.extend_from_slice(&line.origin.includes);
+        let origin = Origin {
+            file: Some(file),
+            includes,
+            expansions: line.origin.expansions.clone(),
+        };
+        self.process(&split_lines(&text, &origin));
+        Ok(())
+    }
+
+    /// Looks for an included file next to the including one,
+    /// or the working directory for sources in memory, and
+    /// then in each include path
+    fn resolve(&self, name: &str, including: Option<&str>) -> Option<PathBuf> {
+        let directory = including
+            .and_then(|including| Path::new(including).parent())
+            .unwrap_or_else(|| Path::new(""));
+
+        std::iter::once(directory)
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Keeps a line as it is, or expands it when it calls a macro
+    fn line(&mut self, line: &SourceLine) -> Result<(), String> {
+        let mut rest = line.text.trim_start();
+
+        // A label before a call points to the first expanded line
+        if let Some(label) = rest
+            .split_whitespace()
+            .next()
+            .filter(|word| Label::is_label(word))
+        {
+            rest = rest[label.len()..].trim_start();
+            if self.find_macro(rest).is_some() {
+                self.lines.push(SourceLine {
+                    text: label.to_string(),
+                    ..line.clone()
+                });
+            }
+        }
+
+        if self.find_macro(rest).is_none() {
+            self.lines.push(line.clone());
+            return Ok(());
+        }
+
+        let (name, rest) = Preprocessor::split_name(rest);
+        let arguments = Preprocessor::split_arguments(rest);
+        let definition = self
+            .macros
+            .iter()
+            .rev()
+            .find(|other| other.name == name && other.parameters.len() == arguments.len())
+            .cloned()
+            .ok_or_else(|| format!("Numero de argumentos invalido para a macro {}", name))?;
+        if line.origin.expansions.len() >= MAX_DEPTH {
+            return Err(format!(
+                "Chamadas de macro aninhadas demais: {}",
+                definition.name
+            ));
+        }
+
+        self.call(&definition, &arguments, line);
+        Ok(())
+    }
+
+    /// Macro named at the start of a line, with any number of parameters
+    fn find_macro(&self, text: &str) -> Option<&Macro> {
+        let (name, _) = Preprocessor::split_name(text);
+        self.macros
+            .iter()
+            .find(|definition| definition.name == name)
+    }
+
+    /// Expands a call, with the parameters replaced by the arguments
+    /// and the labels renamed so each expansion has its own. The new
+    /// names are ".L" local labels, left out of the symbol table
+    fn call(&mut self, definition: &Macro, arguments: &[String], call: &SourceLine) {
+        let id = self.expansions;
+        self.expansions += 1;
+
+        let labels: Vec<&str> = definition
+            .body
+            .iter()
+            .filter_map(|line| line.text.split_whitespace().next())
+            .filter(|word| Label::is_label(word))
+            .map(|word| &word[..word.len() - 1])
+            // Numeric labels are already told apart by their position
+            .filter(|name| !Label::is_numeric(name))
+            .collect();
+
+        let mut expansions = vec![Expansion {
+            name: definition.name.clone(),
+            definition: definition.definition.clone(),
+            call: call.origin.location(call.line),
+        }];
+        expansions.extend_from_slice(&call.origin.expansions);
+
+        let body: Vec<SourceLine> = definition
+            .body
+            .iter()
+            .map(|line| {
+                let text = replace_identifiers(&line.text, |prefix, identifier| {
+                    if prefix == Some('%') {
+                        let parameter = format!("%{}", identifier);
+                        let position =
+                            definition.parameters.iter().position(|p| *p == parameter)?;
+                        Some(arguments[position].clone())
+                    } else if prefix != Some('$') && labels.contains(&identifier) {
+                        Some(format!(".L{}_M{}", identifier, id))
+                    } else {
+                        None
+                    }
+                });
+                SourceLine {
+                    text,
+                    line: line.line,
+                    origin: Origin {
+                        expansions: expansions.clone(),
+                        ..line.origin.clone()
+                    },
+                }
+            })
+            .collect();
+
+        self.process(&body);
+    }
+
+    /// Splits "name(args)" or "name args" into the name and the rest
+    fn split_name(text: &str) -> (&str, &str) {
+        let text = text.trim();
+        let end = text
+            .find(|c: char| c == '(' || c.is_whitespace())
+            .unwrap_or(text.len());
+        (&text[..end], text[end..].trim())
+    }
+
+    /// Arguments or parameters separated by commas, written
+    /// either in parentheses or after the name
+    fn split_arguments(text: &str) -> Vec<String> {
+        let text = match text
+            .strip_prefix('(')
+            .and_then(|text| text.strip_suffix(')'))
+        {
+            Some(inner) => inner,
+            None => text,
+        };
+        if text.trim().is_empty() {
+            return Vec::new();
+        }
+        text.split(',')
+            .map(|argument| argument.trim().to_string())
+            .collect()
+    }
+}
+
+/// Whether both paths name the same file, however they are written
+fn same_file(a: &str, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Rebuilds `text` with each identifier replaced by what `replace`
+/// returns for it, given the character right before the identifier
+fn replace_identifiers(
+    text: &str,
+    mut replace: impl FnMut(Option<char>, &str) -> Option<String>,
+) -> String {
+    let mut replaced = String::new();
+    let mut previous = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if !is_identifier_char(c) {
+            replaced.push(c);
+            previous = Some(c);
+            continue;
+        }
+
+        let mut end = start + c.len_utf8();
+        while let Some(&(index, c)) = chars.peek() {
+            if !is_identifier_char(c) {
+                break;
+            }
+            end = index + c.len_utf8();
+            chars.next();
+        }
+
+        let identifier = &text[start..end];
+        match replace(previous, identifier) {
+            Some(replacement) => {
+                // The "%" or "\" before a parameter is part of what is replaced
+                if matches!(previous, Some('%' | '\\')) {
+                    replaced.pop();
+                }
+                replaced.push_str(&replacement);
+            }
+            None => replaced.push_str(identifier),
+        }
+        previous = identifier.chars().last();
+    }
+
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Assembler, Options, SourceFile};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// Writes the files in a new temporary directory
+    fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("mips_{}_{}", name, std::process::id()));
+        for (path, text) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        directory
+    }
+
+    fn source(directory: &Path, name: &str) -> SourceFile {
+        let path = directory.join(name);
+        SourceFile {
+            text: fs::read_to_string(&path).unwrap(),
+            path: Some(path.display().to_string()),
+        }
+    }
+
+    #[test]
+    fn finds_includes_next_to_the_file_then_in_include_paths() {
+        let directory = directory(
+            "include",
+            &[
+                ("main.s", ".include \"near.s\"\n.include \"far.s\""),
+                ("near.s", "addi $t0, $zero, 1"),
+                ("lib/far.s", "addi $t0, $zero, 2"),
+            ],
+        );
+        let options = Options {
+            include_paths: vec![directory.join("lib")],
+            ..Default::default()
+        };
+        let program = Assembler::from_sources(&[source(&directory, "main.s")], &options)
+            .unwrap()
+            .assemble()
+            .unwrap();
+        assert_eq!(
+            program.section(".text").unwrap().words,
+            [0x20080001, 0x20080002]
+        );
+
+        let errors = Assembler::from_sources(&[source(&directory, "main.s")], &Default::default())
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].message, "Arquivo incluido nao encontrado: far.s");
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn reports_recursive_includes() {
+        let directory = directory(
+            "recursive",
+            &[("a.s", ".include \"b.s\""), ("b.s", ".include \"a.s\"")],
+        );
+        let errors = Assembler::from_sources(&[source(&directory, "a.s")], &Default::default())
+            .err()
+            .unwrap();
+        let file = |name: &str| directory.join(name).display().to_string();
+        assert_eq!(
+            errors[0].message,
+            format!(
+                "Include recursivo: {} -> {} -> {}",
+                file("a.s"),
+                file("b.s"),
+                file("a.s")
+            )
+        );
+        assert_eq!(errors[0].includes.len(), 1);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    fn words(source: &str) -> Vec<u32> {
+        let program = crate::assemble_str(source, &Default::default()).unwrap();
+        program.section(".text").unwrap().words.clone()
+    }
+
+    #[test]
+    fn repeats_blocks() {
+        assert_eq!(words(".rept 2\n.rept 2\nnop\n.endr\n.endr"), [0; 4]);
+        assert_eq!(
+            words(".irp r, $t0, $t1\naddi \\r, \\r, 1\n.endr"),
+            [0x21080001, 0x21290001]
+        );
+    }
+
+    fn texts(source: &str, options: &Options) -> Vec<String> {
+        let source = SourceFile {
+            path: None,
+            text: source.to_string(),
+        };
+        let (lines, _) = super::expand(&[source], options).unwrap();
+        lines.into_iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn expands_macros_with_unique_labels() {
+        let source = "\
+.macro inc(%r)
+loop: addi %r, %r, 1
+bne %r, $zero, loop
+.end_macro
+inc($t0)
+inc($t1)";
+        assert_eq!(
+            texts(source, &Options::default()),
+            [
+                ".Lloop_M0: addi $t0, $t0, 1",
+                "bne $t0, $zero, .Lloop_M0",
+                ".Lloop_M1: addi $t1, $t1, 1",
+                "bne $t1, $zero, .Lloop_M1",
+            ]
+        );
+
+        // A user label with the old name of an expanded one is kept apart
+        let source = ".macro spin\nloop: j loop\n.end_macro\nloop_M0: spin\nj loop_M0";
+        let program = crate::assemble_str(source, &Default::default()).unwrap();
+        let names: Vec<&str> = program.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["loop_M0"]);
+    }
+
+    #[test]
+    fn skips_inactive_blocks() {
+        let source = "\
+.eqv N 2
+.if N - 2
+add $t0, $t0, $t0
+.else
+sub $t0, $t0, $t0
+.endif
+.ifdef FAST
+nop
+.endif";
+        assert_eq!(texts(source, &Options::default()), ["sub $t0, $t0, $t0"]);
+
+        let options = Options {
+            defines: vec![("FAST".to_string(), 1)],
+            ..Default::default()
+        };
+        assert_eq!(texts(source, &options), ["sub $t0, $t0, $t0", "nop"]);
+    }
+
+    #[test]
+    fn takes_the_first_true_elseif() {
+        let source = "\
+.eqv N 2
+.if N == 1
+nop
+.elseif N > 1 && N < 4
+add $t0, $t0, $t0
+.elseif N == 2
+sub $t0, $t0, $t0
+.else
+or $t0, $t0, $t0
+.endif";
+        assert_eq!(texts(source, &Options::default()), ["add $t0, $t0, $t0"]);
+
+        // Conditions in a skipped block are not evaluated
+        let source = ".if 0\n.if UNDEFINED\n.elseif UNDEFINED\n.endif\n.elseif 1\nnop\n.endif";
+        assert_eq!(texts(source, &Options::default()), ["nop"]);
+
+        let source = ".if 1\n.else\n.elseif 1\n.endif\n.elseif 1";
+        let messages: Vec<String> = crate::assemble_str(source, &Default::default())
+            .unwrap_err()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(messages, [".elseif depois de .else", ".elseif sem .if"]);
+    }
+}
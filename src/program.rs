@@ -0,0 +1,58 @@
+/// Address where the text segment starts, as in MARS
+pub const TEXT_ADDRESS: u32 = 0x0040_0000;
+/// Address where the data segment starts, as in MARS
+pub const DATA_ADDRESS: u32 = 0x1001_0000;
+/// Address where the small data sections start, ".sdata" then ".sbss"
+pub const SDATA_ADDRESS: u32 = 0x1000_0000;
+/// Default value of $gp, which reaches the whole 64 KiB
+/// from the start of the small data sections
+pub const GP_ADDRESS: u32 = 0x1000_8000;
+/// Address where the kernel text segment starts, as in MARS
+pub const KTEXT_ADDRESS: u32 = 0x8000_0000;
+/// Address where the kernel data segment starts, as in MARS
+pub const KDATA_ADDRESS: u32 = 0x9000_0000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Directive that opens the section, such as ".text"
+    pub name: String,
+    /// Address of the first word
+    pub address: u32,
+    pub words: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+}
+
+/// Result of assembling a source, ready to be written out
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    /// Sections in the order they start in the source. A section placed
+    /// in pieces, with ".org" or ".align", shows up once for each piece
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+    /// Word for the addresses between sections in a memory image
+    pub fill: u32,
+}
+
+impl Program {
+    /// First piece of the section with the given name
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Sections with instructions, ordered by address, which
+    /// are the contents of a memory image
+    pub fn code(&self) -> Vec<&Section> {
+        let mut code: Vec<&Section> = self
+            .sections
+            .iter()
+            .filter(|section| section.name == ".text" || section.name == ".ktext")
+            .collect();
+        code.sort_by_key(|section| section.address);
+        code
+    }
+}
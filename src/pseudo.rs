@@ -0,0 +1,179 @@
+use crate::{
+    expression::{Constant, Expression},
+    register::{Register, AT, GP},
+    statement::{self, Operand, Statement},
+};
+
+/// Expands the instructions that load addresses, which need more
+/// than one machine instruction and use $at for the upper half:
+///
+/// - "la $t0, label" becomes "lui" and "ori"
+/// - "lw $t0, label" becomes "lui" and "lw" with the lower half as offset
+/// - "sw $t0, label+4($t1)" also adds $t1 to $at before the "sw"
+/// - "lwc1", "swc1", "ldc1" and "sdc1" work as "lw" and "sw"
+///
+/// Labels in `small_data`, defined in ".sdata" or ".sbss", are
+/// reached from $gp instead, with a single instruction
+///
+/// Other lines, including "lw" and "sw" with an offset that only
+/// uses numbers and constants, are left to [`Statement::parse`]
+pub fn expand(
+    text: &str,
+    line: usize,
+    constants: &[Constant],
+    small_data: &[String],
+) -> Result<Option<Vec<Statement>>, String> {
+    let text = text.trim();
+    let name = text.split_whitespace().next().unwrap_or(text);
+    if !["la", "lw", "sw", "lwc1", "swc1", "ldc1", "sdc1"].contains(&name) {
+        return Ok(None);
+    }
+
+    let operands = statement::split_operands(&text[name.len()..]);
+    let invalid = || format!("Operandos invalidos para {}", name);
+    let [register, address] = operands.as_slice() else {
+        return Err(invalid());
+    };
+    // Coprocessor 1 loads and stores move a floating point register
+    let float = name.ends_with("c1");
+    let register = match Register::get_float_register(register) {
+        Some(register) if float => register,
+        _ if float || !register.starts_with('$') => return Err(invalid()),
+        _ => Register::get_register(register)?,
+    };
+    let target = || {
+        if float {
+            Operand::FloatRegister(register)
+        } else {
+            Operand::Register(register)
+        }
+    };
+
+    // "label" or "label($base)", as "offset($base)" is kept
+    // for a plain memory access
+    let (address, base) = match address.rfind('(') {
+        Some(open) if address[open + 1..].trim_start().starts_with('$') => {
+            let base = address[open + 1..]
+                .trim()
+                .strip_suffix(')')
+                .ok_or_else(|| format!("Endereco de memoria invalido: {}", address))?;
+            (address[..open].trim(), Some(Register::get_register(base)?))
+        }
+        _ => (address.as_str(), None),
+    };
+
+    if name == "la" && base.is_some() {
+        return Err(invalid());
+    }
+    // A plain "offset($base)" access, which the offset fits in
+    if base.is_some() && (address.is_empty() || !Expression::parse(address)?.uses_labels(constants))
+    {
+        return Ok(None);
+    }
+    if address.is_empty() {
+        return Err(invalid());
+    }
+
+    // Small data is a single instruction away from $gp
+    if base.is_none() && is_small_data(&Expression::parse(address)?, constants, small_data) {
+        let offset = format!("%gp_rel({})", address);
+        let statement = if name == "la" {
+            Statement::new(
+                "addiu",
+                vec![
+                    Operand::Register(register),
+                    Operand::Register(GP.number),
+                    Operand::Constant(offset),
+                ],
+                line,
+            )
+        } else {
+            Statement::new(
+                name,
+                vec![
+                    target(),
+                    Operand::Memory {
+                        offset,
+                        base: GP.number,
+                    },
+                ],
+                line,
+            )
+        };
+        return Ok(Some(vec![statement]));
+    }
+
+    if name == "la" {
+        return Ok(Some(vec![
+            Statement::new(
+                "lui",
+                vec![
+                    Operand::Register(AT.number),
+                    Operand::Constant(format!("(({}) >> 16) & 0xffff", address)),
+                ],
+                line,
+            ),
+            Statement::new(
+                "ori",
+                vec![
+                    Operand::Register(register),
+                    Operand::Register(AT.number),
+                    Operand::Constant(format!("({}) & 0xffff", address)),
+                ],
+                line,
+            ),
+        ]));
+    }
+
+    if base == Some(AT.number) || (name == "sw" && register == AT.number) {
+        return Err(format!("{} com label nao pode usar $at", name));
+    }
+
+    // The lower half is a signed offset, which %hi makes up for
+    let mut statements = vec![Statement::new(
+        "lui",
+        vec![
+            Operand::Register(AT.number),
+            Operand::Constant(format!("%hi({})", address)),
+        ],
+        line,
+    )];
+    if let Some(base) = base {
+        statements.push(Statement::new(
+            "addu",
+            vec![
+                Operand::Register(AT.number),
+                Operand::Register(AT.number),
+                Operand::Register(base),
+            ],
+            line,
+        ));
+    }
+    statements.push(Statement::new(
+        name,
+        vec![
+            target(),
+            Operand::Memory {
+                offset: format!("%lo({})", address),
+                base: AT.number,
+            },
+        ],
+        line,
+    ));
+    Ok(Some(statements))
+}
+
+/// Whether the address is a label in a small data section,
+/// possibly plus or minus a constant, as in "var+4"
+fn is_small_data(address: &Expression, constants: &[Constant], small_data: &[String]) -> bool {
+    let small = |expression| is_small_data(expression, constants, small_data);
+    match address {
+        Expression::Symbol(name) => small_data.contains(name),
+        Expression::Binary("+", left, right) => {
+            (small(left) && !right.uses_labels(constants))
+                || (small(right) && !left.uses_labels(constants))
+        }
+        Expression::Binary("-", left, right) => small(left) && !right.uses_labels(constants),
+        _ => false,
+    }
+}
@@ -129,18 +129,114 @@ pub const REGISTERS: [Register; 32] = [
     },
 ];
 
+/// Defines a constant for each entry of REGISTERS,
+/// so code can name registers without parsing them
+macro_rules! register_constants {
+    ($($constant:ident = $index:literal,)*) => {
+        $(pub const $constant: Register = REGISTERS[$index];)*
+    };
+}
+
+register_constants! {
+    ZERO = 0,
+    AT = 1,
+    V0 = 2,
+    V1 = 3,
+    A0 = 4,
+    A1 = 5,
+    A2 = 6,
+    A3 = 7,
+    T0 = 8,
+    T1 = 9,
+    T2 = 10,
+    T3 = 11,
+    T4 = 12,
+    T5 = 13,
+    T6 = 14,
+    T7 = 15,
+    S0 = 16,
+    S1 = 17,
+    S2 = 18,
+    S3 = 19,
+    S4 = 20,
+    S5 = 21,
+    S6 = 22,
+    S7 = 23,
+    T8 = 24,
+    T9 = 25,
+    K0 = 26,
+    K1 = 27,
+    GP = 28,
+    SP = 29,
+    FP = 30,
+    RA = 31,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Register {
     pub number: u8,
     pub name: &'static str,
 }
 
+/// Coprocessor 1 register, "$f0" to "$f31"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloatRegister {
+    pub number: u8,
+}
+
+/// Defines a constant for each coprocessor 1 register
+macro_rules! float_register_constants {
+    ($($constant:ident = $number:literal,)*) => {
+        $(pub const $constant: FloatRegister = FloatRegister { number: $number };)*
+    };
+}
+
+float_register_constants! {
+    F0 = 0,
+    F1 = 1,
+    F2 = 2,
+    F3 = 3,
+    F4 = 4,
+    F5 = 5,
+    F6 = 6,
+    F7 = 7,
+    F8 = 8,
+    F9 = 9,
+    F10 = 10,
+    F11 = 11,
+    F12 = 12,
+    F13 = 13,
+    F14 = 14,
+    F15 = 15,
+    F16 = 16,
+    F17 = 17,
+    F18 = 18,
+    F19 = 19,
+    F20 = 20,
+    F21 = 21,
+    F22 = 22,
+    F23 = 23,
+    F24 = 24,
+    F25 = 25,
+    F26 = 26,
+    F27 = 27,
+    F28 = 28,
+    F29 = 29,
+    F30 = 30,
+    F31 = 31,
+}
+
 impl Register {
-    fn named_to_number(name: &str) -> u8 {
+    pub fn named_to_number(name: &str) -> Result<u8, String> {
         REGISTERS
             .iter()
             .find(|register| register.name == name)
-            .expect("Registrador nao encontrado")
-            .number
+            .map(|register| register.number)
+            .ok_or_else(|| format!("Registrador nao encontrado: ${}", name))
+    }
+
+    pub fn get_name(number: u8) -> &'static str {
+        REGISTERS[number as usize].name
     }
 
     fn filter_string(unfiltered_string: &str) -> String {
@@ -152,10 +248,17 @@ impl Register {
         register.as_str().to_string()
     }
 
-    pub fn get_register(unfiltered_string: &str) -> u8 {
+    /// Number of a coprocessor 1 register, "$f0" to "$f31"
+    pub fn get_float_register(text: &str) -> Option<u8> {
+        let number = text.strip_prefix("$f")?.parse::<u8>().ok()?;
+        (number < 32).then_some(number)
+    }
+
+    pub fn get_register(unfiltered_string: &str) -> Result<u8, String> {
         let register_string = Register::filter_string(unfiltered_string);
         match register_string.parse::<u8>() {
-            Ok(value) => return value,
+            Ok(value) if (value as usize) < REGISTERS.len() => Ok(value),
+            Ok(_) => Err(format!("Registrador invalido: {}", unfiltered_string)),
             Err(_) => Register::named_to_number(register_string.as_ref()),
         }
     }
@@ -0,0 +1,464 @@
+use crate::{
+    instruction::Instruction,
+    instruction_format::{InstructionFormat, BSHFL},
+    preprocessor::Origin,
+    register::{Register, RA},
+};
+
+pub enum Operand {
+    Register(u8),
+    /// Coprocessor 1 register, "$f0" to "$f31"
+    FloatRegister(u8),
+    /// Number or label, resolved when encoding
+    Constant(String),
+    /// "offset($base)" memory reference
+    Memory {
+        offset: String,
+        base: u8,
+    },
+}
+
+#[derive(PartialEq)]
+enum OperandKind {
+    Register,
+    FloatRegister,
+    Constant,
+    Memory,
+}
+
+impl Operand {
+    fn kind(&self) -> OperandKind {
+        match self {
+            Operand::Register(_) => OperandKind::Register,
+            Operand::FloatRegister(_) => OperandKind::FloatRegister,
+            Operand::Constant(_) => OperandKind::Constant,
+            Operand::Memory { .. } => OperandKind::Memory,
+        }
+    }
+
+    /// Parses a single operand, where a memory reference is
+    /// an offset expression followed by "($base)"
+    fn parse(operand: &str) -> Result<Operand, String> {
+        if operand.is_empty() {
+            return Err("Operando vazio".to_string());
+        }
+        if let Some(register) = Register::get_float_register(operand) {
+            return Ok(Operand::FloatRegister(register));
+        }
+        if operand.starts_with('$') {
+            return Ok(Operand::Register(Register::get_register(operand)?));
+        }
+
+        if let Some(open) = operand.rfind('(') {
+            let base = operand[open + 1..].trim_start();
+            if base.starts_with('$') {
+                let base = base
+                    .strip_suffix(')')
+                    .ok_or_else(|| format!("Endereco de memoria invalido: {}", operand))?;
+                let offset = match operand[..open].trim() {
+                    "" => "0",
+                    offset => offset,
+                };
+                return Ok(Operand::Memory {
+                    offset: offset.to_string(),
+                    base: Register::get_register(base.trim())?,
+                });
+            }
+        }
+
+        Ok(Operand::Constant(operand.to_string()))
+    }
+}
+
+/// Splits the operands of an instruction or directive at commas. Spaces
+/// also separate operands when they could not be inside an expression,
+/// as in "add $t0 $t1 $t2", while "end - start" and "8 ($sp)" are kept
+pub fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut chars = text.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        // Character literals such as ',' and ' ' are kept whole
+        if c == '\'' {
+            current.push(c);
+            while let Some(c) = chars.next() {
+                current.push(c);
+                match c {
+                    '\\' => current.extend(chars.next()),
+                    '\'' => break,
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ if c.is_whitespace() && depth == 0 => {
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+                let rest: String = chars.clone().collect();
+                if separates(current.trim(), &rest) {
+                    operands.push(current.trim().to_string());
+                    current.clear();
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+    operands
+}
+
+/// Whether a space between an operand written so far and the rest
+/// of the line separates two operands, instead of being inside one
+fn separates(operand: &str, rest: &str) -> bool {
+    if operand.is_empty() || rest.is_empty() || rest.starts_with(',') {
+        return false;
+    }
+    // Registers are not used in expressions
+    if operand.starts_with('$') && !rest.starts_with('(') {
+        return true;
+    }
+    let ends_operand = operand
+        .chars()
+        .last()
+        .is_some_and(|c| c.is_ascii_alphanumeric() || "_.)'".contains(c));
+    ends_operand && starts_operand(rest)
+}
+
+fn starts_operand(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('%') => chars.next().is_some_and(|c| c.is_ascii_alphabetic()),
+        Some(c) => c.is_ascii_alphanumeric() || "_.$'".contains(c),
+        None => false,
+    }
+}
+
+/// A source line holding an instruction, kept in program order
+/// so later passes can inspect or rearrange it before encoding
+pub struct Statement {
+    pub instruction: &'static Instruction,
+    pub operands: Vec<Operand>,
+    /// Source line, starting at 1
+    pub line: usize,
+    pub origin: Origin,
+}
+
+impl Statement {
+    /// Parses a line without its label, such as "add $t0, $t1, $t2"
+    pub fn parse(text: &str, line: usize) -> Result<Statement, String> {
+        let text = text.trim();
+        let name = text.split_whitespace().next().unwrap_or(text);
+        let instruction = Instruction::get_instruction(name)
+            .ok_or_else(|| format!("Instrucao nao encontrada: {}", name))?;
+
+        let mut operands = split_operands(&text[name.len()..])
+            .iter()
+            .map(|operand| Operand::parse(operand))
+            .collect::<Result<Vec<Operand>, String>>()?;
+        // The code of syscall and break is optional
+        if operands.is_empty() && matches!(name, "syscall" | "break") {
+            operands.push(Operand::Constant("0".to_string()));
+        }
+
+        let expected = Statement::expected_operands(instruction);
+        let kinds: Vec<OperandKind> = operands.iter().map(Operand::kind).collect();
+        if kinds != expected {
+            return Err(format!("Operandos invalidos para {}", instruction.name));
+        }
+
+        Ok(Statement {
+            instruction,
+            operands,
+            line,
+            origin: Origin::default(),
+        })
+    }
+
+    /// Builds a statement from operands that are already parsed
+    /// and match what the instruction expects
+    pub fn new(name: &str, operands: Vec<Operand>, line: usize) -> Statement {
+        let instruction = Instruction::get_instruction(name).expect("Instrucao nao encontrada");
+        debug_assert!(
+            operands.iter().map(Operand::kind).collect::<Vec<_>>()
+                == Statement::expected_operands(instruction),
+            "Operandos invalidos para {}",
+            name
+        );
+
+        Statement {
+            instruction,
+            operands,
+            line,
+            origin: Origin::default(),
+        }
+    }
+
+    fn expected_operands(instruction: &Instruction) -> Vec<OperandKind> {
+        use OperandKind::*;
+
+        match instruction.name {
+            "nop" | "eret" => vec![],
+            "syscall" | "break" => vec![Constant],
+            "jr" | "mfhi" | "mflo" => vec![Register],
+            "mult" | "multu" | "div" | "divu" | "madd" | "maddu" | "msub" | "msubu" | "tge"
+            | "tgeu" | "tlt" | "tltu" | "teq" | "tne" => vec![Register, Register],
+            "clz" | "clo" => vec![Register, Register],
+            "sll" | "srl" => vec![Register, Register, Constant],
+            "lw" | "sw" => vec![Register, Memory],
+            "lui" => vec![Register, Constant],
+            "beq" | "bne" => vec![Register, Register, Constant],
+            "lwc1" | "swc1" | "ldc1" | "sdc1" => vec![FloatRegister, Memory],
+            "mfc1" | "mtc1" => vec![Register, FloatRegister],
+            "add.s" | "add.d" | "sub.s" | "sub.d" | "mul.s" | "mul.d" | "div.s" | "div.d" => {
+                vec![FloatRegister, FloatRegister, FloatRegister]
+            }
+            _ => match instruction.format {
+                InstructionFormat::R { .. } => vec![Register, Register, Register],
+                InstructionFormat::I { .. } => vec![Register, Register, Constant],
+                InstructionFormat::J { .. } => vec![Constant],
+                InstructionFormat::RegImm { .. } => vec![Register, Constant],
+                InstructionFormat::Cop0 { .. } => vec![Register, Register],
+                // Compares, conversions and the other one operand operations
+                InstructionFormat::FR { .. } => vec![FloatRegister, FloatRegister],
+                InstructionFormat::FI { .. } => vec![Constant],
+                // ext and ins take the position and size of the bit field
+                InstructionFormat::Special3 { function, .. } if function != BSHFL => {
+                    vec![Register, Register, Constant, Constant]
+                }
+                InstructionFormat::Special3 { .. } => vec![Register, Register],
+                InstructionFormat::Rotate { .. } => match instruction.name {
+                    "rotr" => vec![Register, Register, Constant],
+                    _ => vec![Register, Register, Register],
+                },
+            },
+        }
+    }
+
+    pub fn nop(line: usize) -> Statement {
+        Statement::new("nop", vec![], line)
+    }
+
+    /// Register at the given operand, which is
+    /// known to be one after parsing
+    pub fn register(&self, index: usize) -> u8 {
+        match &self.operands[index] {
+            Operand::Register(register) | Operand::FloatRegister(register) => *register,
+            Operand::Memory { base, .. } => *base,
+            Operand::Constant(_) => panic!("Operando {} nao e um registrador", index),
+        }
+    }
+
+    /// Constant at the given operand, which is
+    /// known to be one after parsing
+    pub fn constant(&self, index: usize) -> &str {
+        match &self.operands[index] {
+            Operand::Constant(constant) => constant,
+            Operand::Memory { offset, .. } => offset,
+            Operand::Register(_) | Operand::FloatRegister(_) => {
+                panic!("Operando {} nao e uma constante", index)
+            }
+        }
+    }
+
+    pub fn is_nop(&self) -> bool {
+        self.instruction.name == "nop"
+    }
+
+    pub fn is_load(&self) -> bool {
+        ["lw", "lwc1", "ldc1"].contains(&self.instruction.name)
+    }
+
+    /// Whether the operand at `index` is a double precision value
+    pub fn is_double(&self, index: usize) -> bool {
+        match self
+            .instruction
+            .name
+            .split('.')
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            ["ldc1" | "sdc1"] => true,
+            ["cvt", to, from] => [to, from][index.min(1)] == &"d",
+            [_, .., "d"] => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_branch(&self) -> bool {
+        ["beq", "bne", "bc1t", "bc1f"].contains(&self.instruction.name)
+    }
+
+    /// Operand with the offset of a branch, which is the last one
+    pub fn branch_offset_index(&self) -> usize {
+        self.operands.len() - 1
+    }
+
+    /// Whether this instruction changes the control flow, meaning
+    /// the following instructions may not be the next to execute
+    pub fn is_branch_or_jump(&self) -> bool {
+        self.is_branch() || ["jr", "j", "jal"].contains(&self.instruction.name)
+    }
+
+    /// General purpose register written by this instruction, if any.
+    /// Writes to $zero are discarded by the hardware and are not reported
+    pub fn written_register(&self) -> Option<u8> {
+        let register = match self.instruction.format {
+            InstructionFormat::R { .. } => {
+                let writes_none = [
+                    "nop", "mult", "multu", "div", "divu", "madd", "maddu", "msub", "msubu", "jr",
+                    "syscall", "break", "tge", "tgeu", "tlt", "tltu", "teq", "tne",
+                ];
+                if writes_none.contains(&self.instruction.name) {
+                    return None;
+                }
+                self.register(0)
+            }
+            InstructionFormat::I { .. } => {
+                let writes_none = ["sw", "beq", "bne", "lwc1", "swc1", "ldc1", "sdc1"];
+                if writes_none.contains(&self.instruction.name) {
+                    return None;
+                }
+                self.register(0)
+            }
+            InstructionFormat::Special3 { .. } | InstructionFormat::Rotate { .. } => {
+                self.register(0)
+            }
+            InstructionFormat::J { .. } => {
+                if self.instruction.name != "jal" {
+                    return None;
+                }
+                RA.number
+            }
+            InstructionFormat::RegImm { .. } | InstructionFormat::FI { .. } => return None,
+            // Only moves from coprocessor 1 write general purpose registers
+            InstructionFormat::FR { .. } => {
+                if self.instruction.name != "mfc1" {
+                    return None;
+                }
+                self.register(0)
+            }
+            InstructionFormat::Cop0 { .. } => {
+                if self.instruction.name != "mfc0" {
+                    return None;
+                }
+                self.register(0)
+            }
+        };
+
+        if register == 0 {
+            return None;
+        }
+        Some(register)
+    }
+
+    /// Coprocessor 1 registers written by this instruction. A double
+    /// takes an even register and the one after it, so both are given
+    pub fn written_float_registers(&self) -> Vec<u8> {
+        let name = self.instruction.name;
+        match self.instruction.format {
+            InstructionFormat::I { .. } if ["lwc1", "ldc1"].contains(&name) => {
+                self.float_registers(&[0])
+            }
+            InstructionFormat::FR { .. } => match name {
+                "mtc1" => self.float_registers(&[1]),
+                // Compares only set the condition flag
+                "mfc1" => vec![],
+                _ if name.starts_with("c.") => vec![],
+                _ => self.float_registers(&[0]),
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Coprocessor 1 registers read by this instruction,
+    /// with both registers of a double
+    pub fn read_float_registers(&self) -> Vec<u8> {
+        let name = self.instruction.name;
+        match self.instruction.format {
+            InstructionFormat::I { .. } if ["swc1", "sdc1"].contains(&name) => {
+                self.float_registers(&[0])
+            }
+            InstructionFormat::FR { .. } => match name {
+                "mtc1" => vec![],
+                "mfc1" => self.float_registers(&[1]),
+                _ if name.starts_with("c.") => self.float_registers(&[0, 1]),
+                _ => self.float_registers(&(1..self.operands.len()).collect::<Vec<_>>()),
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Registers at the given operands, followed by
+    /// the odd register of the ones holding a double
+    fn float_registers(&self, operands: &[usize]) -> Vec<u8> {
+        let mut registers = Vec::new();
+        for &index in operands {
+            let register = self.register(index);
+            registers.push(register);
+            if self.is_double(index) {
+                registers.push(register + 1);
+            }
+        }
+        registers
+    }
+
+    /// General purpose registers read by this instruction
+    pub fn read_registers(&self) -> Vec<u8> {
+        let name = self.instruction.name;
+        match self.instruction.format {
+            InstructionFormat::R { .. } => match name {
+                "nop" | "mfhi" | "mflo" | "syscall" | "break" => vec![],
+                "jr" => vec![self.register(0)],
+                "mult" | "multu" | "div" | "divu" | "madd" | "maddu" | "msub" | "msubu" | "tge"
+                | "tgeu" | "tlt" | "tltu" | "teq" | "tne" => {
+                    vec![self.register(0), self.register(1)]
+                }
+                "sll" | "srl" | "clz" | "clo" => vec![self.register(1)],
+                _ => vec![self.register(1), self.register(2)],
+            },
+            InstructionFormat::I { .. } => match name {
+                "lw" | "lwc1" | "swc1" | "ldc1" | "sdc1" => vec![self.register(1)],
+                "sw" | "beq" | "bne" => vec![self.register(0), self.register(1)],
+                "lui" => vec![],
+                _ => vec![self.register(1)],
+            },
+            InstructionFormat::J { .. } => vec![],
+            InstructionFormat::RegImm { .. } => vec![self.register(0)],
+            InstructionFormat::Cop0 { .. } => match name {
+                "mtc0" => vec![self.register(0)],
+                _ => vec![],
+            },
+            InstructionFormat::FR { .. } => match name {
+                "mtc1" => vec![self.register(0)],
+                _ => vec![],
+            },
+            InstructionFormat::FI { .. } => vec![],
+            // ins keeps the bits of rt outside the field
+            InstructionFormat::Special3 { .. } => match name {
+                "ins" => vec![self.register(0), self.register(1)],
+                _ => vec![self.register(1)],
+            },
+            InstructionFormat::Rotate { .. } => match name {
+                "rotr" => vec![self.register(1)],
+                _ => vec![self.register(1), self.register(2)],
+            },
+        }
+    }
+}